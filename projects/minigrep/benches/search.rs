@@ -0,0 +1,83 @@
+//! Compares the line by line `search` with the memory-mapped backend,
+//! which jumps from hit to hit with a vectorised substring search.
+//!
+//! Run with `cargo bench`, it prints the throughput of each in MB/s.
+//! Jumping pays off when hits are rare, a few times faster than `lines()`
+//! here. When nearly every line matches it does more work per line and
+//! ends up a bit slower.
+
+use std::{
+    fs::{self, File},
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use memmap2::Mmap;
+
+// About 64 MB of log-like lines
+const LINES: usize = 1_000_000;
+
+fn main() {
+    let contents = haystack();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("haystack.log");
+    fs::write(&path, &contents).unwrap();
+
+    // A needle on a few lines, and one on nearly every line
+    for needle in ["request 4242 failed", "request"] {
+        println!("needle {needle:?}, {} MB", contents.len() / 1_000_000);
+
+        bench("search (lines + contains)", contents.len(), || {
+            minigrep::search(needle, &contents).len()
+        });
+
+        bench("literal_lines in memory", contents.len(), || {
+            minigrep::literal_lines(needle, contents.as_bytes()).count()
+        });
+
+        bench("literal_lines read", contents.len(), || {
+            let contents = fs::read(&path).unwrap();
+            minigrep::literal_lines(needle, &contents).count()
+        });
+
+        bench("literal_lines mmap", contents.len(), || {
+            let file = File::open(&path).unwrap();
+            let map = unsafe { Mmap::map(&file).unwrap() };
+            minigrep::literal_lines(needle, &map).count()
+        });
+
+        println!();
+    }
+}
+
+fn haystack() -> String {
+    let mut contents = String::new();
+    for i in 0..LINES {
+        let status = if i % 100_000 == 4242 { "failed" } else { "ok" };
+        contents.push_str(&format!(
+            "2024-01-01T00:00:00Z INFO server: request {} {status} in {}ms\n",
+            i % 10_000,
+            i % 97
+        ));
+    }
+    contents
+}
+
+// Run `f` for about a second and print its throughput
+fn bench(name: &str, bytes: usize, mut f: impl FnMut() -> usize) {
+    // Once to warm up, and to check every backend finds the same lines
+    let found = black_box(f());
+
+    let started = Instant::now();
+    let mut runs = 0;
+    while started.elapsed() < Duration::from_secs(1) {
+        black_box(f());
+        runs += 1;
+    }
+
+    let per_run = started.elapsed() / runs;
+    let throughput = bytes as f64 / per_run.as_secs_f64() / 1_000_000.0;
+    println!(
+        "  {name:<28} {per_run:>10.2?} {throughput:>8.0} MB/s  ({found} lines)"
+    );
+}
@@ -0,0 +1,1214 @@
+use std::{
+    env,
+    error::Error,
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+};
+
+use crate::walk;
+
+#[derive(Debug, Default)]
+pub struct Config {
+    // A line is selected if any of them matches, usually there is
+    // only the one from the command line
+    pub patterns: Vec<String>,
+    // Files or directories, directories are searched recursively
+    pub paths: Vec<String>,
+    pub ignore_case: bool,
+    // Ignore case unless the query has an uppercase letter
+    pub smart_case: bool,
+    pub regex: bool,
+    // Also match the patterns with up to this many typos
+    pub fuzzy: Option<usize>,
+    // Only match whole words, or whole lines
+    pub word_regexp: bool,
+    pub line_regexp: bool,
+    // Search each file as a whole, so a match can span lines
+    pub multiline: bool,
+    // Always read files, even the big ones worth memory mapping
+    pub no_mmap: bool,
+    // Search binary files as if they were text
+    pub text: bool,
+    pub encoding: Encoding,
+    // Decompress every file that looks compressed, not only the ones
+    // named like it
+    pub search_zip: bool,
+    // Only search files of these types, or not of these types
+    pub types: Vec<String>,
+    pub types_not: Vec<String>,
+    // More types, like "name:*.ext"
+    pub type_add: Vec<String>,
+    // Only search files matching these, or not matching the ones
+    // starting with !
+    pub globs: Vec<String>,
+    // Search hidden files and directories
+    pub hidden: bool,
+    // Don't respect .gitignore and .ignore files
+    pub no_ignore: bool,
+    pub line_number: bool,
+    // Print the column of the first hit, implies line_number
+    pub column: bool,
+    // Print the offset of each line from the start of the file
+    pub byte_offset: bool,
+    // Select the lines that don't match
+    pub invert_match: bool,
+    // Only print the number of selected lines per file
+    pub count: bool,
+    // Count the hits instead of the lines, goes with count
+    pub count_matches: bool,
+    // Print numbers about the whole search at the end
+    pub stats: bool,
+    // Only print the files with, or without, a selected line
+    pub files_with_matches: bool,
+    pub files_without_match: bool,
+    // Stop reading a file after this many selected lines
+    pub max_count: Option<usize>,
+    // Print nothing, only the exit status tells whether there was a match
+    pub quiet: bool,
+    // Lines of context to print before and after each selected line
+    pub before_context: usize,
+    pub after_context: usize,
+    // Number of threads to search with, 0 picks one per CPU
+    pub threads: usize,
+    // Walk and print the files in order of their name
+    pub sort_files: bool,
+    // Keep searching the lines appended to the files, like tail -f
+    pub follow: bool,
+    // Print JSON Lines instead of text
+    pub json: bool,
+    // Print the selected lines with each match replaced by this
+    pub replace: Option<String>,
+    // Write the replacements back to the files instead, or only show
+    // the changes as a diff
+    pub in_place: bool,
+    pub dry_run: bool,
+    pub color: Color,
+}
+
+/// When to highlight the output with ANSI colors.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Color {
+    // Only when printing to a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// What the files searched are encoded in.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    // UTF-16 if there is a byte order mark, UTF-8 otherwise
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+/// A command line option, used both for parsing and for `--help`.
+struct Opt {
+    short: Option<char>,
+    long: &'static str,
+    // Name of the value shown in the help, None for plain flags
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+impl Opt {
+    // -e and -f, which make the first positional argument a path
+    fn gives_patterns(&self) -> bool {
+        matches!(self.long, "regexp" | "file")
+    }
+}
+
+const OPTIONS: &[Opt] = &[
+    Opt {
+        short: Some('e'),
+        long: "regexp",
+        value: Some("PATTERN"),
+        help: "Search for PATTERN, can be given more than once",
+    },
+    Opt {
+        short: Some('f'),
+        long: "file",
+        value: Some("FILE"),
+        help: "Search for the patterns in FILE, one per line",
+    },
+    Opt {
+        short: Some('i'),
+        long: "ignore-case",
+        value: None,
+        help: "Search case insensitively (or set IGNORE_CASE)",
+    },
+    Opt {
+        short: Some('S'),
+        long: "smart-case",
+        value: None,
+        help: "Search case insensitively if QUERY is all lowercase",
+    },
+    Opt {
+        short: Some('s'),
+        long: "case-sensitive",
+        value: None,
+        help: "Search case sensitively, undoes -i, -S and IGNORE_CASE",
+    },
+    Opt {
+        short: Some('E'),
+        long: "regex",
+        value: None,
+        help: "Treat QUERY as a regular expression",
+    },
+    Opt {
+        short: None,
+        long: "fuzzy",
+        value: Some("NUM"),
+        help: "Also match QUERY with up to NUM typos, prints ~typos per hit",
+    },
+    Opt {
+        short: Some('w'),
+        long: "word-regexp",
+        value: None,
+        help: "Only match whole words",
+    },
+    Opt {
+        short: Some('x'),
+        long: "line-regexp",
+        value: None,
+        help: "Only match whole lines",
+    },
+    Opt {
+        short: Some('U'),
+        long: "multiline",
+        value: None,
+        help: "Let matches span lines, like '\\n' or '\\s+' with -E",
+    },
+    Opt {
+        short: Some('n'),
+        long: "line-number",
+        value: None,
+        help: "Prefix each line with its line number",
+    },
+    Opt {
+        short: None,
+        long: "column",
+        value: None,
+        help: "Prefix each line with the column of the first match",
+    },
+    Opt {
+        short: Some('b'),
+        long: "byte-offset",
+        value: None,
+        help: "Prefix each line with its byte offset in the file",
+    },
+    Opt {
+        short: Some('v'),
+        long: "invert-match",
+        value: None,
+        help: "Select the lines that don't match",
+    },
+    Opt {
+        short: Some('c'),
+        long: "count",
+        value: None,
+        help: "Only print the number of selected lines per file",
+    },
+    Opt {
+        short: None,
+        long: "count-matches",
+        value: None,
+        help: "Only print the number of matches per file",
+    },
+    Opt {
+        short: Some('l'),
+        long: "files-with-matches",
+        value: None,
+        help: "Only print the paths of files with a match",
+    },
+    Opt {
+        short: Some('L'),
+        long: "files-without-match",
+        value: None,
+        help: "Only print the paths of files without a match",
+    },
+    Opt {
+        short: Some('m'),
+        long: "max-count",
+        value: Some("NUM"),
+        help: "Stop reading a file after NUM selected lines",
+    },
+    Opt {
+        short: Some('q'),
+        long: "quiet",
+        value: None,
+        help: "Print nothing, exit with 0 on the first match",
+    },
+    Opt {
+        short: Some('A'),
+        long: "after-context",
+        value: Some("NUM"),
+        help: "Print NUM lines after each match",
+    },
+    Opt {
+        short: Some('B'),
+        long: "before-context",
+        value: Some("NUM"),
+        help: "Print NUM lines before each match",
+    },
+    Opt {
+        short: Some('C'),
+        long: "context",
+        value: Some("NUM"),
+        help: "Print NUM lines before and after each match",
+    },
+    Opt {
+        short: None,
+        long: "color",
+        value: Some("WHEN"),
+        help: "Highlight matches: auto (default), always or never",
+    },
+    Opt {
+        short: None,
+        long: "stats",
+        value: None,
+        help: "Print how much was searched and found at the end",
+    },
+    Opt {
+        short: None,
+        long: "json",
+        value: None,
+        help: "Print results as JSON Lines, documented on minigrep::run",
+    },
+    Opt {
+        short: Some('r'),
+        long: "replace",
+        value: Some("TEMPLATE"),
+        help: "Replace each match with TEMPLATE, $1 is a group with -E",
+    },
+    Opt {
+        short: None,
+        long: "in-place",
+        value: None,
+        help: "Write the replacements back to the text files",
+    },
+    Opt {
+        short: None,
+        long: "dry-run",
+        value: None,
+        help: "Print what --in-place would change as a diff",
+    },
+    Opt {
+        short: None,
+        long: "no-mmap",
+        value: None,
+        help: "Never memory map large files, read them instead",
+    },
+    Opt {
+        short: Some('a'),
+        long: "text",
+        value: None,
+        help: "Search binary files as if they were text",
+    },
+    Opt {
+        short: None,
+        long: "encoding",
+        value: Some("ENC"),
+        help: "auto (default), utf-8, utf-16le, utf-16be or latin1",
+    },
+    Opt {
+        short: Some('z'),
+        long: "search-zip",
+        value: None,
+        help: "Decompress gzip, zstd and bzip2 files, not only *.gz etc.",
+    },
+    Opt {
+        short: Some('t'),
+        long: "type",
+        value: Some("TYPE"),
+        help: "Only search files of TYPE, like rust or markdown",
+    },
+    Opt {
+        short: Some('T'),
+        long: "type-not",
+        value: Some("TYPE"),
+        help: "Don't search files of TYPE",
+    },
+    Opt {
+        short: None,
+        long: "type-add",
+        value: Some("DEF"),
+        help: "Define a type for -t and -T, like 'web:*.{html,css}'",
+    },
+    Opt {
+        short: Some('g'),
+        long: "glob",
+        value: Some("GLOB"),
+        help: "Only search files matching GLOB, skip them with '!GLOB'",
+    },
+    Opt {
+        short: None,
+        long: "hidden",
+        value: None,
+        help: "Search hidden files and directories",
+    },
+    Opt {
+        short: None,
+        long: "no-ignore",
+        value: None,
+        help: "Don't respect .gitignore and .ignore files",
+    },
+    Opt {
+        short: Some('j'),
+        long: "threads",
+        value: Some("NUM"),
+        help: "Search NUM files at once, 0 picks one per CPU (default)",
+    },
+    Opt {
+        short: None,
+        long: "sort-files",
+        value: None,
+        help: "Print files in order of their name",
+    },
+    Opt {
+        short: None,
+        long: "follow",
+        value: None,
+        help: "Search lines as they are appended to the files, like tail -f",
+    },
+    Opt {
+        short: None,
+        long: "no-config",
+        value: None,
+        help: "Don't read the config file",
+    },
+    Opt {
+        short: Some('h'),
+        long: "help",
+        value: None,
+        help: "Print this help and exit",
+    },
+    Opt {
+        short: Some('V'),
+        long: "version",
+        value: None,
+        help: "Print the version and exit",
+    },
+];
+
+impl Config {
+    // fn new(args: &[String]) -> Config {
+    //     if args.len() < 3 {
+    //         panic!("not enough arguments");
+    //     }
+
+    //     let query = args[1].clone();
+    //     let file_path = args[2].clone();
+
+    //     Config { query, file_path }
+    // }
+
+    // We want to let caller handle the Error, calling panic
+    // is a good choice
+    /// Parse the command line on top of the defaults.
+    ///
+    /// Later settings win: first the flags in the config file, then the
+    /// IGNORE_CASE environment variable, then the flags on the command
+    /// line. Flags that can be given more than once, like -e or -g, add
+    /// to the ones from the config file.
+    pub fn build(
+        mut args: impl Iterator<Item = String>,
+    ) -> Result<Config, ConfigError> {
+        // Skip file path
+        args.next();
+        let args: Vec<String> = args.collect();
+
+        // Looked for before parsing, the config file could be broken
+        let defaults = match no_config(&args) {
+            true => None,
+            false => config_file()?,
+        };
+
+        Config::parse(defaults, env::var("IGNORE_CASE").is_ok(), args)
+    }
+
+    // build, with whatever it read from the file system and the
+    // environment handed in
+    fn parse(
+        defaults: Option<(String, Vec<String>)>,
+        ignore_case: bool,
+        args: Vec<String>,
+    ) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+
+        if let Some((path, defaults)) = defaults {
+            let file_error = |reason: String| ConfigError::ConfigFile {
+                path: path.clone(),
+                reason,
+            };
+            let mut positional = Vec::new();
+            config
+                .parse_flags(defaults.into_iter(), &mut positional, false)
+                .map_err(|err| file_error(err.to_string()))?;
+            // Patterns and paths only come from the command line
+            if let Some(arg) = positional.first() {
+                return Err(file_error(format!("'{arg}' isn't a flag")));
+            }
+        }
+
+        if ignore_case {
+            config.ignore_case = true;
+        }
+
+        let mut positional = Vec::new();
+        // With -e or -f every positional argument is a path, even if
+        // the pattern file was empty
+        let patterns_given =
+            config.parse_flags(args.into_iter(), &mut positional, true)?;
+
+        let mut positional = positional.into_iter();
+
+        if !patterns_given {
+            match positional.next() {
+                Some(arg) => config.patterns.push(arg),
+                None => return Err(ConfigError::MissingQuery),
+            }
+        }
+
+        // Every remaining argument is a file or directory
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            return Err(ConfigError::MissingPath);
+        }
+
+        walk::check_filters(&config)?;
+        if config.fuzzy.is_some() && config.regex {
+            return Err(ConfigError::Conflicts("--fuzzy", "--regex"));
+        }
+        // The closest hit may not be a word when a hit a bit further
+        // away would be
+        if config.fuzzy.is_some() && config.word_regexp {
+            return Err(ConfigError::Conflicts("--fuzzy", "--word-regexp"));
+        }
+        if config.in_place && config.replace.is_none() {
+            return Err(ConfigError::Needs("--in-place", "--replace"));
+        }
+        if config.dry_run && !config.in_place {
+            return Err(ConfigError::Needs("--dry-run", "--in-place"));
+        }
+        // The lines -v selects have nothing to replace, and rewriting the
+        // others instead would change the lines that weren't asked for
+        if config.in_place && config.invert_match {
+            return Err(ConfigError::Conflicts("--in-place", "--invert-match"));
+        }
+        // Files are written back as the UTF-8 they were read as, not
+        // decompressed or transcoded
+        if config.in_place {
+            let utf8 =
+                matches!(config.encoding, Encoding::Auto | Encoding::Utf8);
+            let conflicts =
+                [(config.search_zip, "--search-zip"), (!utf8, "--encoding")];
+            if let Some((_, flag)) = conflicts.iter().find(|(set, _)| *set) {
+                return Err(ConfigError::Conflicts("--in-place", flag));
+            }
+        }
+        // Lines are all there is to these
+        if config.multiline {
+            let conflicts = [
+                (config.invert_match, "--invert-match"),
+                (config.line_regexp, "--line-regexp"),
+                (config.in_place, "--in-place"),
+                (config.follow, "--follow"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(set, _)| *set) {
+                return Err(ConfigError::Conflicts("--multiline", flag));
+            }
+        }
+        // Following never gets to the end of a file
+        if config.follow {
+            let conflicts = [
+                (config.count, "--count"),
+                (config.files_with_matches, "--files-with-matches"),
+                (config.files_without_match, "--files-without-match"),
+                (config.in_place, "--in-place"),
+            ];
+            if let Some((_, flag)) = conflicts.iter().find(|(set, _)| *set) {
+                return Err(ConfigError::Conflicts("--follow", flag));
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Set the flags in `args`, the other arguments go to `positional`.
+    // Without `patterns` -e and -f are refused, like positional arguments
+    // in the config file. Returns whether -e or -f gave the patterns.
+    fn parse_flags(
+        &mut self,
+        mut args: impl Iterator<Item = String>,
+        positional: &mut Vec<String>,
+        patterns: bool,
+    ) -> Result<bool, ConfigError> {
+        let mut patterns_given = false;
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                // Everything after -- is positional, even if it
+                // looks like a flag
+                positional.extend(args.by_ref());
+            } else if let Some(long) = arg.strip_prefix("--") {
+                // Either --flag, --flag=value or --flag value
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let opt = OPTIONS
+                    .iter()
+                    .find(|opt| opt.long == name)
+                    .ok_or_else(|| ConfigError::UnknownFlag(arg.clone()))?;
+                let value = match (opt.value, value) {
+                    (Some(_), Some(value)) => Some(value),
+                    (Some(_), None) => Some(
+                        args.next()
+                            .ok_or(ConfigError::MissingValue(arg.clone()))?,
+                    ),
+                    (None, Some(_)) => {
+                        return Err(ConfigError::UnexpectedValue(format!(
+                            "--{name}"
+                        )))
+                    }
+                    (None, None) => None,
+                };
+                if opt.gives_patterns() {
+                    if !patterns {
+                        return Err(ConfigError::CommandLineOnly(format!(
+                            "--{name}"
+                        )));
+                    }
+                    patterns_given = true;
+                }
+                self.set(opt, value)?;
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // Short flags can be combined, -in is -i -n
+                // A flag taking a value eats the rest, -A3 or -A 3
+                for (i, c) in arg[1..].char_indices() {
+                    let opt =
+                        OPTIONS
+                            .iter()
+                            .find(|opt| opt.short == Some(c))
+                            .ok_or(ConfigError::UnknownFlag(format!("-{c}")))?;
+                    if opt.value.is_none() {
+                        self.set(opt, None)?;
+                        continue;
+                    }
+
+                    if opt.gives_patterns() {
+                        if !patterns {
+                            return Err(ConfigError::CommandLineOnly(format!(
+                                "-{c}"
+                            )));
+                        }
+                        patterns_given = true;
+                    }
+                    let rest = &arg[1 + i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .ok_or(ConfigError::MissingValue(format!("-{c}")))?
+                    } else {
+                        rest.to_string()
+                    };
+                    self.set(opt, Some(value))?;
+                    break;
+                }
+            } else {
+                // A lone "-" is positional too
+                positional.push(arg);
+            }
+        }
+
+        Ok(patterns_given)
+    }
+
+    /// Whether the selected lines themselves get printed, rather than
+    /// only a count, a path or nothing at all.
+    pub fn prints_lines(&self) -> bool {
+        !(self.count
+            || self.files_with_matches
+            || self.files_without_match
+            || self.quiet)
+    }
+
+    /// Whether every hit has to be counted, not only the lines with one.
+    pub fn counts_matches(&self) -> bool {
+        self.count_matches || self.stats || self.json
+    }
+
+    fn set(
+        &mut self,
+        opt: &Opt,
+        value: Option<String>,
+    ) -> Result<(), ConfigError> {
+        // Options taking a value always get one from build
+        let value = value.unwrap_or_default();
+
+        match opt.long {
+            "regexp" => self.patterns.push(value),
+            "file" => {
+                let patterns = fs::read_to_string(&value).map_err(|err| {
+                    ConfigError::PatternFile {
+                        path: value.clone(),
+                        reason: err.to_string(),
+                    }
+                })?;
+                self.patterns.extend(patterns.lines().map(String::from));
+            }
+            "ignore-case" => self.ignore_case = true,
+            "smart-case" => self.smart_case = true,
+            "case-sensitive" => {
+                self.ignore_case = false;
+                self.smart_case = false;
+            }
+            "regex" => self.regex = true,
+            "fuzzy" => self.fuzzy = Some(number(opt, value)?),
+            "word-regexp" => self.word_regexp = true,
+            "line-regexp" => self.line_regexp = true,
+            "multiline" => self.multiline = true,
+            "line-number" => self.line_number = true,
+            "column" => self.column = true,
+            "byte-offset" => self.byte_offset = true,
+            "invert-match" => self.invert_match = true,
+            "count" => self.count = true,
+            // -c, but of the matches
+            "count-matches" => {
+                self.count = true;
+                self.count_matches = true;
+            }
+            "stats" => self.stats = true,
+            "files-with-matches" => self.files_with_matches = true,
+            "files-without-match" => self.files_without_match = true,
+            "max-count" => self.max_count = Some(number(opt, value)?),
+            "quiet" => self.quiet = true,
+            "after-context" => self.after_context = number(opt, value)?,
+            "before-context" => self.before_context = number(opt, value)?,
+            "context" => {
+                self.after_context = number(opt, value)?;
+                self.before_context = self.after_context;
+            }
+            "threads" => self.threads = number(opt, value)?,
+            "sort-files" => self.sort_files = true,
+            "follow" => self.follow = true,
+            "json" => self.json = true,
+            "replace" => self.replace = Some(value),
+            "in-place" => self.in_place = true,
+            "dry-run" => self.dry_run = true,
+            "color" => {
+                self.color = match value.as_str() {
+                    "auto" => Color::Auto,
+                    "always" => Color::Always,
+                    "never" => Color::Never,
+                    _ => return Err(invalid(opt, value)),
+                }
+            }
+            "no-mmap" => self.no_mmap = true,
+            "text" => self.text = true,
+            "encoding" => {
+                self.encoding = match value.to_lowercase().as_str() {
+                    "auto" => Encoding::Auto,
+                    "utf-8" | "utf8" => Encoding::Utf8,
+                    "utf-16le" | "utf16le" => Encoding::Utf16Le,
+                    "utf-16be" | "utf16be" => Encoding::Utf16Be,
+                    "latin1" | "latin-1" | "iso-8859-1" => Encoding::Latin1,
+                    _ => return Err(invalid(opt, value)),
+                }
+            }
+            "search-zip" => self.search_zip = true,
+            "type" => self.types.push(value),
+            "type-not" => self.types_not.push(value),
+            "type-add" => self.type_add.push(value),
+            "glob" => self.globs.push(value),
+            "hidden" => self.hidden = true,
+            "no-ignore" => self.no_ignore = true,
+            // build has taken care of it already
+            "no-config" => {}
+            "help" => return Err(ConfigError::Help),
+            "version" => return Err(ConfigError::Version),
+            _ => unreachable!("option --{} is not handled", opt.long),
+        }
+
+        Ok(())
+    }
+}
+
+// Where the config file is, from the home directory, unless
+// MINIGREP_CONFIG says otherwise
+const CONFIG_FILE: &str = ".config/minigrep/config";
+
+// The path of the config file and the flags in it, if there is one.
+// The one MINIGREP_CONFIG names has to exist, the default one doesn't.
+fn config_file() -> Result<Option<(String, Vec<String>)>, ConfigError> {
+    let (path, required) = match env::var_os("MINIGREP_CONFIG") {
+        Some(path) if !path.is_empty() => (path.into(), true),
+        _ => match env::var_os("HOME") {
+            Some(home) => (Path::new(&home).join(CONFIG_FILE), false),
+            None => return Ok(None),
+        },
+    };
+
+    let path_text = path.display().to_string();
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some((path_text, config_args(&contents)))),
+        Err(err) if !required && err.kind() == io::ErrorKind::NotFound => {
+            Ok(None)
+        }
+        Err(err) => Err(ConfigError::ConfigFile {
+            path: path_text,
+            reason: err.to_string(),
+        }),
+    }
+}
+
+// Whether --no-config is one of the flags in `args`, and not the value
+// of another one like in `-e --no-config`
+fn no_config(args: &[String]) -> bool {
+    let takes_value = |opt: &Opt| opt.value.is_some();
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--no-config" {
+            return true;
+        }
+        let value_next = if let Some(long) = arg.strip_prefix("--") {
+            !long.contains('=')
+                && OPTIONS
+                    .iter()
+                    .any(|opt| opt.long == long && takes_value(opt))
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            // The first short flag taking a value eats the rest
+            let mut chars = arg[1..].chars();
+            chars.by_ref().any(|c| {
+                OPTIONS
+                    .iter()
+                    .any(|opt| opt.short == Some(c) && takes_value(opt))
+            }) && chars.as_str().is_empty()
+        } else {
+            false
+        };
+        if value_next {
+            args.next();
+        }
+    }
+    false
+}
+
+// One argument per line, like "--glob=!target/**" or "-A" then "2".
+// Blank lines and lines starting with # are skipped.
+fn config_args(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn number(opt: &Opt, value: String) -> Result<usize, ConfigError> {
+    value.parse().map_err(|_| invalid(opt, value))
+}
+
+fn invalid(opt: &Opt, value: String) -> ConfigError {
+    ConfigError::InvalidValue {
+        flag: format!("--{}", opt.long),
+        value,
+    }
+}
+
+/// The text printed by `--help`.
+pub fn help() -> String {
+    let mut help = String::from(
+        "Search for QUERY in each PATH, directories are searched recursively\n\
+         \n\
+         Exits with 0 if a line was selected, 1 if not and 2 on errors.\n\
+         \n\
+         Default flags are read from ~/.config/minigrep/config, or the file\n\
+         MINIGREP_CONFIG names, one per line. IGNORE_CASE wins over the\n\
+         config file and the flags given here win over both.\n\
+         \n\
+         Usage: minigrep [OPTIONS] QUERY PATH...\n\
+         \x20      minigrep [OPTIONS] -e PATTERN... PATH...\n\
+         \x20      minigrep [OPTIONS] -f FILE... PATH...\n\
+         \n\
+         Options:\n",
+    );
+
+    for opt in OPTIONS {
+        let short = match opt.short {
+            Some(c) => format!("-{c}, "),
+            None => String::from("    "),
+        };
+        let long = match opt.value {
+            Some(value) => format!("--{} {value}", opt.long),
+            None => format!("--{}", opt.long),
+        };
+        help.push_str(&format!("  {short}{long:<24} {}\n", opt.help));
+    }
+
+    help.trim_end().to_string()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    MissingQuery,
+    MissingPath,
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { flag: String, value: String },
+    // The io::Error as text, so that ConfigError stays comparable
+    PatternFile { path: String, reason: String },
+    ConfigFile { path: String, reason: String },
+    // -e or -f anywhere but on the command line
+    CommandLineOnly(String),
+    // A flag that only makes sense together with another one
+    Needs(&'static str, &'static str),
+    Conflicts(&'static str, &'static str),
+    // Not really errors, the caller should print the help or
+    // the version and exit successfully
+    Help,
+    Version,
+}
+
+impl Error for ConfigError {}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "Didn't get a query string"),
+            ConfigError::MissingPath => write!(f, "Didn't get a file path"),
+            ConfigError::UnknownFlag(flag) => {
+                write!(f, "Unknown flag '{flag}'")
+            }
+            ConfigError::MissingValue(flag) => {
+                write!(f, "Flag '{flag}' needs a value")
+            }
+            ConfigError::UnexpectedValue(flag) => {
+                write!(f, "Flag '{flag}' doesn't take a value")
+            }
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "Invalid value '{value}' for flag '{flag}'")
+            }
+            ConfigError::PatternFile { path, reason } => {
+                write!(f, "Couldn't read patterns from '{path}': {reason}")
+            }
+            ConfigError::ConfigFile { path, reason } => {
+                write!(f, "Bad config file '{path}': {reason}")
+            }
+            ConfigError::CommandLineOnly(flag) => {
+                write!(f, "Flag '{flag}' only works on the command line")
+            }
+            ConfigError::Needs(flag, other) => {
+                write!(f, "Flag '{flag}' needs '{other}' as well")
+            }
+            ConfigError::Conflicts(flag, other) => {
+                write!(f, "Flags '{flag}' and '{other}' can't be used together")
+            }
+            ConfigError::Help => write!(f, "{}", help()),
+            ConfigError::Version => {
+                write!(f, "minigrep {}", env!("CARGO_PKG_VERSION"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without a config file or IGNORE_CASE, whatever the machine has
+    fn build(args: &[&str]) -> Result<Config, ConfigError> {
+        let args = args.iter().map(|s| s.to_string()).collect();
+        Config::parse(None, false, args)
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config =
+            build(&["-n", "--invert-match", "to", "poem.txt", "src"]).unwrap();
+
+        assert!(config.line_number);
+        assert!(config.invert_match);
+        assert!(!config.count);
+        assert_eq!(vec!["to"], config.patterns);
+        assert_eq!(vec!["poem.txt", "src"], config.paths);
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let config = build(&["to", "-inc", "poem.txt"]).unwrap();
+
+        assert!(config.ignore_case);
+        assert!(config.line_number);
+        assert!(config.count);
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = build(&["-n", "--", "-v", "--count"]).unwrap();
+
+        assert!(!config.invert_match);
+        assert_eq!(vec!["-v"], config.patterns);
+        assert_eq!(vec!["--count"], config.paths);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Some(ConfigError::MissingQuery), build(&[]).err());
+        assert_eq!(Some(ConfigError::MissingPath), build(&["to"]).err());
+        assert_eq!(
+            Some(ConfigError::UnknownFlag(String::from("-k"))),
+            build(&["-nk", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::UnknownFlag(String::from("--nope"))),
+            build(&["--nope", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::UnexpectedValue(String::from("--count"))),
+            build(&["--count=3", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Needs("--in-place", "--replace")),
+            build(&["--in-place", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Needs("--dry-run", "--in-place")),
+            build(&["--dry-run", "-r", "at", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflicts("--in-place", "--invert-match")),
+            build(&["-v", "-r", "at", "--in-place", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflicts("--in-place", "--encoding")),
+            build(&[
+                "-r",
+                "at",
+                "--in-place",
+                "--encoding=latin1",
+                "to",
+                "poem.txt"
+            ])
+            .err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflicts("--fuzzy", "--regex")),
+            build(&["--fuzzy=1", "-E", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::Conflicts("--fuzzy", "--word-regexp")),
+            build(&["--fuzzy=1", "-w", "to", "poem.txt"]).err()
+        );
+        assert_eq!(Some(ConfigError::Help), build(&["-h"]).err());
+        assert_eq!(Some(ConfigError::Version), build(&["--version"]).err());
+    }
+
+    #[test]
+    fn flag_values() {
+        let config = build(&["-A2", "-B", "3", "to", "poem.txt"]).unwrap();
+        assert_eq!((3, 2), (config.before_context, config.after_context));
+
+        let config = build(&["-nC1", "to", "poem.txt"]).unwrap();
+        assert_eq!((1, 1), (config.before_context, config.after_context));
+
+        let config = build(&["--context=4", "to", "poem.txt"]).unwrap();
+        assert_eq!((4, 4), (config.before_context, config.after_context));
+
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                flag: String::from("--after-context"),
+                value: String::from("x"),
+            }),
+            build(&["-A", "x", "to", "poem.txt"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::MissingValue(String::from("-C"))),
+            build(&["to", "poem.txt", "-C"]).err()
+        );
+    }
+
+    #[test]
+    fn color() {
+        let config = build(&["to", "poem.txt"]).unwrap();
+        assert_eq!(Color::Auto, config.color);
+
+        let config = build(&["--color=never", "to", "poem.txt"]).unwrap();
+        assert_eq!(Color::Never, config.color);
+
+        let config = build(&["--color", "always", "to", "poem.txt"]).unwrap();
+        assert_eq!(Color::Always, config.color);
+
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                flag: String::from("--color"),
+                value: String::from("sometimes"),
+            }),
+            build(&["--color=sometimes", "to", "poem.txt"]).err()
+        );
+    }
+
+    #[test]
+    fn file_filters() {
+        let config = build(&[
+            "-trust",
+            "--type-add=web:*.html",
+            "-t",
+            "web",
+            "-Tmarkdown",
+            "-g",
+            "!target/**",
+            "to",
+            "src",
+        ])
+        .unwrap();
+        assert_eq!(vec!["rust", "web"], config.types);
+        assert_eq!(vec!["markdown"], config.types_not);
+        assert_eq!(vec!["!target/**"], config.globs);
+
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                flag: String::from("--type"),
+                value: String::from("web"),
+            }),
+            build(&["-t", "web", "to", "src"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                flag: String::from("--glob"),
+                value: String::from("src/{a"),
+            }),
+            build(&["--glob=src/{a", "to", "src"]).err()
+        );
+    }
+
+    #[test]
+    fn config_file_defaults() {
+        let contents =
+            "# Always want these\n-n\n\n  --glob=!target/**\n-A\n2\n";
+        assert_eq!(
+            vec!["-n", "--glob=!target/**", "-A", "2"],
+            config_args(contents)
+        );
+
+        let parse = |defaults: &[&str], ignore_case, args: &[&str]| {
+            let defaults = defaults.iter().map(|s| s.to_string()).collect();
+            let args = args.iter().map(|s| s.to_string()).collect();
+            Config::parse(
+                Some((String::from("rc"), defaults)),
+                ignore_case,
+                args,
+            )
+        };
+
+        // Flags add to the lists and override the rest
+        let config = parse(
+            &["-n", "--glob=!target/**", "-A", "2"],
+            false,
+            &["-g*.rs", "-A5", "to", "src"],
+        )
+        .unwrap();
+        assert!(config.line_number);
+        assert_eq!(vec!["!target/**", "*.rs"], config.globs);
+        assert_eq!(5, config.after_context);
+
+        // The config file, then IGNORE_CASE, then the flags
+        assert!(parse(&["-s"], true, &["to", "src"]).unwrap().ignore_case);
+        assert!(
+            !parse(&["-i"], true, &["-s", "to", "src"])
+                .unwrap()
+                .ignore_case
+        );
+
+        assert_eq!(
+            Some(ConfigError::ConfigFile {
+                path: String::from("rc"),
+                reason: String::from("'src' isn't a flag"),
+            }),
+            parse(&["-n", "src"], false, &["to", "src"]).err()
+        );
+        assert_eq!(
+            Some(ConfigError::ConfigFile {
+                path: String::from("rc"),
+                reason: String::from("Unknown flag '-k'"),
+            }),
+            parse(&["-k"], false, &["to", "src"]).err()
+        );
+        // Patterns only come from the command line too
+        for defaults in [&["-e", "foo"][..], &["-nffoo.txt"], &["--file=x"]] {
+            assert!(matches!(
+                parse(defaults, false, &["to", "src"]),
+                Err(ConfigError::ConfigFile { .. })
+            ));
+        }
+        assert_eq!(
+            Some(ConfigError::ConfigFile {
+                path: String::from("rc"),
+                reason: String::from(
+                    "Flag '--regexp' only works on the command line"
+                ),
+            }),
+            parse(&["--regexp", "foo"], false, &["to", "src"]).err()
+        );
+
+        // --no-config is only a flag where a flag can be
+        let args = |args: &[&str]| -> Vec<String> {
+            args.iter().map(|s| s.to_string()).collect()
+        };
+        assert!(no_config(&args(&["--no-config", "to", "src"])));
+        assert!(no_config(&args(&["-nA", "2", "--no-config", "to", "src"])));
+        assert!(!no_config(&args(&["-e", "--no-config", "src"])));
+        assert!(!no_config(&args(&["-nA", "--no-config", "to", "src"])));
+        assert!(!no_config(&args(&["--", "--no-config", "src"])));
+    }
+
+    #[test]
+    fn encoding() {
+        let config = build(&["to", "poem.txt"]).unwrap();
+        assert_eq!(Encoding::Auto, config.encoding);
+
+        let config = build(&["--encoding=Latin-1", "to", "poem.txt"]).unwrap();
+        assert_eq!(Encoding::Latin1, config.encoding);
+
+        assert_eq!(
+            Some(ConfigError::InvalidValue {
+                flag: String::from("--encoding"),
+                value: String::from("ebcdic"),
+            }),
+            build(&["--encoding", "ebcdic", "to", "poem.txt"]).err()
+        );
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = build(&["-e", "to", "--regexp=be", "poem.txt"]).unwrap();
+        assert_eq!(vec!["to", "be"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("patterns");
+        fs::write(&file, "frog\r\nbog\n").unwrap();
+        let file = file.to_str().unwrap();
+
+        let config = build(&["-eto", "-f", file, "poem.txt"]).unwrap();
+        assert_eq!(vec!["to", "frog", "bog"], config.patterns);
+
+        // An empty file means no patterns, not that QUERY comes next
+        fs::write(file, "").unwrap();
+        let config = build(&["-f", file, "poem.txt"]).unwrap();
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["poem.txt"], config.paths);
+
+        assert!(matches!(
+            build(&["-f", "no/such/file", "poem.txt"]),
+            Err(ConfigError::PatternFile { .. })
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::VecDeque, io};
+
+use crate::{Match, Sink};
+
+/// Decides which lines around the selected ones of a file get printed
+/// as context and where the `--` separators between groups go.
+///
+/// Lines are fed one at a time, so overlapping windows merge naturally:
+/// a line is never printed twice and a separator only shows up when
+/// lines were skipped.
+pub struct Context {
+    before: usize,
+    after: usize,
+    // The last `before` lines that weren't printed
+    buffer: VecDeque<Match<'static>>,
+    // How many lines are still to be printed after the last match
+    after_left: usize,
+    // Line number of the last printed line
+    last_printed: Option<usize>,
+}
+
+impl Context {
+    pub fn new(before: usize, after: usize) -> Context {
+        Context {
+            before,
+            after,
+            buffer: VecDeque::with_capacity(before),
+            after_left: 0,
+            last_printed: None,
+        }
+    }
+
+    pub fn selected<S: Sink>(
+        &mut self,
+        m: Match,
+        sink: &mut S,
+    ) -> io::Result<()> {
+        let first = self.buffer.front().unwrap_or(&m).line_number;
+        self.separate(first, sink)?;
+
+        for context in self.buffer.drain(..) {
+            sink.context(&context)?;
+        }
+        sink.matched(&m)?;
+
+        self.after_left = self.after;
+        self.last_printed = Some(m.last_line_number());
+        Ok(())
+    }
+
+    pub fn other<S: Sink>(&mut self, m: Match, sink: &mut S) -> io::Result<()> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            sink.context(&m)?;
+            self.last_printed = Some(m.line_number);
+        } else if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            // The line is about to be overwritten by the next one
+            self.buffer.push_back(m.into_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Whether lines after the last match are still to be printed.
+    pub fn wants_more(&self) -> bool {
+        self.after_left > 0
+    }
+
+    // Groups only get separated when there is context to tell apart.
+    // Telling files apart is up to the sink.
+    fn separate<S: Sink>(&self, first: usize, sink: &mut S) -> io::Result<()> {
+        if self.before == 0 && self.after == 0 {
+            return Ok(());
+        }
+
+        if self.last_printed.is_some_and(|last| first > last + 1) {
+            sink.context_break()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{printer::Printer, Config};
+
+    // Print the lines of `contents` containing "x" with the context
+    fn print(contents: &str, before: usize, after: usize) -> String {
+        let mut out = Vec::new();
+        let config = Config {
+            line_number: true,
+            ..Config::default()
+        };
+        let mut printer = Printer::new(&mut out, &config, false);
+        let mut context = Context::new(before, after);
+
+        for (index, line) in contents.lines().enumerate() {
+            let m = Match {
+                line: line.into(),
+                line_number: index + 1,
+                byte_offset: 0,
+                columns: Vec::new(),
+                distances: Vec::new(),
+            };
+            if line.contains('x') {
+                context.selected(m, &mut printer).unwrap();
+            } else {
+                context.other(m, &mut printer).unwrap();
+            }
+        }
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn after_and_before() {
+        let contents = "a\nx\nb\nc\nd\ne\nx\nf";
+
+        assert_eq!("2:x\n3-b\n--\n7:x\n8-f\n", print(contents, 0, 1));
+        assert_eq!("1-a\n2:x\n--\n6-e\n7:x\n", print(contents, 1, 0));
+    }
+
+    #[test]
+    fn overlapping_windows_merge() {
+        let contents = "a\nx\nb\nc\nx\nd\ne\nf";
+
+        assert_eq!(
+            "1-a\n2:x\n3-b\n4-c\n5:x\n6-d\n7-e\n",
+            print(contents, 2, 2)
+        );
+    }
+
+    #[test]
+    fn adjacent_windows_have_no_separator() {
+        let contents = "x\na\nb\nx";
+
+        assert_eq!("1:x\n2-a\n3-b\n4:x\n", print(contents, 1, 1));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    ffi::OsStr,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use ruzstd::decoding::StreamingDecoder;
+
+/// The compressed formats we can search, recognized by the first bytes
+/// of the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Format {
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Format::Zstd)
+        } else if header.starts_with(b"BZh") {
+            Some(Format::Bzip2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the name of `path` says it's compressed, those files are
+/// decompressed even without -z.
+pub fn has_extension(path: &Path) -> bool {
+    let extension = path.extension().and_then(OsStr::to_str);
+    matches!(extension, Some("gz" | "zst" | "bz2"))
+}
+
+/// `reader`, decompressed if it starts like one of the formats, or as
+/// it is otherwise.
+///
+/// Only peeks at the start of the reader, nothing is consumed until the
+/// search reads from what we return.
+pub fn reader<'a, R: BufRead + 'a>(
+    mut reader: R,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let reader: Box<dyn BufRead> = match Format::detect(reader.fill_buf()?) {
+        // The multi decoders keep going after the first member, that's
+        // what `cat a.gz b.gz` makes and gzip reads
+        Some(Format::Gzip) => {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        }
+        Some(Format::Bzip2) => {
+            Box::new(BufReader::new(MultiBzDecoder::new(reader)))
+        }
+        // Only the first frame though, which is all zstd writes
+        Some(Format::Zstd) => {
+            let decoder = StreamingDecoder::new(reader).map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            })?;
+            Box::new(BufReader::new(decoder))
+        }
+        None => Box::new(reader),
+    };
+    Ok(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    const TEXT: &[u8] = b"one\ntwo\nthree\n";
+
+    fn decompress(compressed: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        reader(compressed).unwrap().read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn each_format() {
+        let mut gzip =
+            flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        gzip.write_all(TEXT).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let mut bzip2 =
+            bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+        bzip2.write_all(TEXT).unwrap();
+        let bzip2 = bzip2.finish().unwrap();
+
+        let zstd = ruzstd::encoding::compress_to_vec(
+            TEXT,
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+
+        assert_eq!(Some(Format::Gzip), Format::detect(&gzip));
+        assert_eq!(Some(Format::Bzip2), Format::detect(&bzip2));
+        assert_eq!(Some(Format::Zstd), Format::detect(&zstd));
+        for compressed in [gzip, bzip2, zstd] {
+            assert_eq!(TEXT, decompress(&compressed));
+        }
+    }
+
+    #[test]
+    fn plain_text_is_left_alone() {
+        assert_eq!(None, Format::detect(TEXT));
+        assert_eq!(TEXT, decompress(TEXT));
+    }
+
+    #[test]
+    fn extensions() {
+        assert!(has_extension(Path::new("logs/app.log.1.gz")));
+        assert!(has_extension(Path::new("app.log.zst")));
+        assert!(!has_extension(Path::new("app.log")));
+    }
+}
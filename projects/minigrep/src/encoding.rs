@@ -0,0 +1,191 @@
+use std::io::{self, BufRead};
+
+use crate::Encoding;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16BE_BOM: &[u8] = b"\xfe\xff";
+
+/// `reader` transcoded to UTF-8 from `encoding`.
+///
+/// `Encoding::Auto` goes by the byte order mark, UTF-16 with a BOM is
+/// transcoded and anything else is taken as UTF-8. A BOM is never part
+/// of the text searched. Offsets are those of the UTF-8 text.
+pub fn reader<'a, R: BufRead + 'a>(
+    mut reader: R,
+    encoding: Encoding,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    let start = reader.fill_buf()?;
+    let encoding = match encoding {
+        Encoding::Auto if start.starts_with(UTF16LE_BOM) => Encoding::Utf16Le,
+        Encoding::Auto if start.starts_with(UTF16BE_BOM) => Encoding::Utf16Be,
+        Encoding::Auto => Encoding::Utf8,
+        encoding => encoding,
+    };
+
+    let bom = match encoding {
+        Encoding::Utf8 => UTF8_BOM,
+        Encoding::Utf16Le => UTF16LE_BOM,
+        Encoding::Utf16Be => UTF16BE_BOM,
+        _ => b"",
+    };
+    if !bom.is_empty() && start.starts_with(bom) {
+        reader.consume(bom.len());
+    }
+
+    Ok(match encoding {
+        Encoding::Utf8 | Encoding::Auto => Box::new(reader),
+        _ => Box::new(Decoder {
+            inner: reader,
+            encoding,
+            undecoded: Vec::new(),
+            out: Vec::new(),
+            pos: 0,
+        }),
+    })
+}
+
+// Decodes UTF-16 or Latin-1 a chunk of the inner reader at a time
+struct Decoder<R> {
+    inner: R,
+    encoding: Encoding,
+    // Bytes at the end of a chunk that need the next one, half a UTF-16
+    // code unit or the first half of a surrogate pair
+    undecoded: Vec<u8>,
+    // UTF-8 ready to be read
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Decoder<R> {
+    // Decode the next chunk into `out`, false at the end
+    fn decode(&mut self) -> io::Result<bool> {
+        let chunk = self.inner.fill_buf()?;
+        if chunk.is_empty() {
+            if self.undecoded.is_empty() {
+                return Ok(false);
+            }
+            // The file ended in the middle of a character
+            self.undecoded.clear();
+            self.out.extend_from_slice("\u{fffd}".as_bytes());
+            return Ok(true);
+        }
+
+        let read = chunk.len();
+        self.undecoded.extend_from_slice(chunk);
+        self.inner.consume(read);
+
+        let mut text = String::with_capacity(self.undecoded.len());
+        let decoded = match self.encoding {
+            // Latin-1 bytes are the first 256 code points
+            Encoding::Latin1 => {
+                text.extend(self.undecoded.iter().map(|&b| char::from(b)));
+                self.undecoded.len()
+            }
+            _ => {
+                let big_endian = self.encoding == Encoding::Utf16Be;
+                let mut units: Vec<u16> = self
+                    .undecoded
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        let pair = [pair[0], pair[1]];
+                        match big_endian {
+                            true => u16::from_be_bytes(pair),
+                            false => u16::from_le_bytes(pair),
+                        }
+                    })
+                    .collect();
+                // The other half of a pair is in the next chunk
+                if units.last().is_some_and(|u| (0xd800..0xdc00).contains(u)) {
+                    units.pop();
+                }
+
+                let decoded = units.len() * 2;
+                text.extend(
+                    char::decode_utf16(units)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+                decoded
+            }
+        };
+
+        self.undecoded.drain(..decoded);
+        self.out.extend_from_slice(text.as_bytes());
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> io::Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Decoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A chunk can decode to nothing, a lone byte of UTF-16
+        while self.pos == self.out.len() {
+            self.out.clear();
+            self.pos = 0;
+            if !self.decode()? {
+                break;
+            }
+        }
+        Ok(&self.out[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos += amount;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: Encoding) -> String {
+        // Tiny chunks, so characters get split between them
+        let inner = BufReader::with_capacity(3, bytes);
+        let mut text = String::new();
+        reader(inner, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn utf16_by_bom() {
+        let text = "héllo 🦀\n";
+        let le: Vec<u8> =
+            text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> =
+            text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+
+        assert_eq!(text, decode(&[UTF16LE_BOM, &le].concat(), Encoding::Auto));
+        assert_eq!(text, decode(&[UTF16BE_BOM, &be].concat(), Encoding::Auto));
+        // Without a BOM only if we're told
+        assert_eq!(text, decode(&le, Encoding::Utf16Le));
+    }
+
+    #[test]
+    fn broken_utf16() {
+        // A lone low surrogate, and half a code unit at the end
+        assert_eq!(
+            "a\u{fffd}\u{fffd}",
+            decode(b"a\x00\x00\xdc\x62", Encoding::Utf16Le)
+        );
+    }
+
+    #[test]
+    fn latin1_and_utf8_bom() {
+        assert_eq!("café", decode(b"caf\xe9", Encoding::Latin1));
+        assert_eq!("café", decode("\u{feff}café".as_bytes(), Encoding::Auto));
+    }
+}
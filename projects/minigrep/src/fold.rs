@@ -0,0 +1,194 @@
+use std::ops::Range;
+
+/// A literal query matched case insensitively with Unicode case folding.
+///
+/// The query is folded once up front, lines are folded a character at a
+/// time while comparing, so nothing gets allocated per line and the
+/// ranges found point into the original line. Folding can turn one
+/// character into several, so `STRASSE` finds `Straße` and the other way
+/// around, but a match never ends halfway through a character.
+#[derive(Debug)]
+pub struct CaseFolded {
+    query: Vec<char>,
+}
+
+impl CaseFolded {
+    pub fn new(query: &str) -> CaseFolded {
+        CaseFolded {
+            query: query.chars().flat_map(fold).collect(),
+        }
+    }
+
+    /// Byte range of the first match in `line` starting at or after
+    /// `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        // Like str::find, an empty query is found anywhere
+        let Some(&first) = self.query.first() else {
+            return Some(start..start);
+        };
+
+        line[start..].char_indices().find_map(|(i, c)| {
+            // Cheap check before walking the rest of the line
+            if fold(c).first() != Some(first) {
+                return None;
+            }
+            let at = start + i;
+            self.match_at(line, at).map(|end| at..end)
+        })
+    }
+
+    // Where the match starting at byte `at` ends, if there is one
+    fn match_at(&self, line: &str, at: usize) -> Option<usize> {
+        let mut query = self.query.iter();
+
+        for (i, c) in line[at..].char_indices() {
+            for folded in fold(c) {
+                // Also no match when the query runs out halfway
+                // through a character
+                if query.next() != Some(&folded) {
+                    return None;
+                }
+            }
+            if query.len() == 0 {
+                return Some(at + i + c.len_utf8());
+            }
+        }
+
+        None
+    }
+}
+
+/// Whether `query` has an uppercase letter, for `--smart-case`.
+/// Characters escaped with a backslash don't count, so that regex
+/// classes like `\W` or `\S` don't make a search case sensitive.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut escaped = false;
+    query.chars().any(|c| {
+        let upper = c.is_uppercase() && !escaped;
+        escaped = regex && c == '\\' && !escaped;
+        upper
+    })
+}
+
+/// The case folding of a single character, up to three characters.
+pub struct Folded {
+    chars: [char; 3],
+    len: usize,
+    next: usize,
+}
+
+impl Folded {
+    fn first(&self) -> Option<char> {
+        (self.len > 0).then_some(self.chars[0])
+    }
+}
+
+impl Iterator for Folded {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.next == self.len {
+            return None;
+        }
+        self.next += 1;
+        Some(self.chars[self.next - 1])
+    }
+}
+
+/// Fold `c` for comparing case insensitively.
+///
+/// Mostly `char::to_lowercase`, plus the full foldings lowercasing
+/// misses. The Turkish dotted and dotless i both fold to a plain `i`,
+/// so `istanbul` finds `İstanbul` and `ISTANBUL` finds `ıstanbul`.
+pub fn fold(c: char) -> Folded {
+    let special = match c {
+        'ß' | 'ẞ' => "ss",
+        'ς' => "σ",
+        'ſ' => "s",
+        'İ' | 'ı' => "i",
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        'ﬅ' | 'ﬆ' => "st",
+        _ => "",
+    };
+
+    let mut chars = ['\0'; 3];
+    let mut len = 0;
+    if special.is_empty() {
+        for lower in c.to_lowercase() {
+            chars[len] = lower;
+            len += 1;
+        }
+    } else {
+        for folded in special.chars() {
+            chars[len] = folded;
+            len += 1;
+        }
+    }
+
+    Folded {
+        chars,
+        len,
+        next: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_all(query: &str, line: &str) -> Vec<Range<usize>> {
+        let query = CaseFolded::new(query);
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while let Some(range) = query.find_at(line, start) {
+            start = range.end;
+            ranges.push(range);
+        }
+        ranges
+    }
+
+    #[test]
+    fn ascii() {
+        assert_eq!(vec![0..4, 7..11], find_all("rUsT", "Rust, trust"));
+    }
+
+    #[test]
+    fn sharp_s_folds_to_ss() {
+        assert_eq!(vec![0..7], find_all("STRASSE", "Straße"));
+        assert_eq!(vec![0..7], find_all("straße", "STRASSE"));
+        // Half of a ß is no match
+        assert!(find_all("stras", "Straße").is_empty());
+    }
+
+    #[test]
+    fn turkish_i() {
+        assert_eq!(vec![0..9], find_all("istanbul", "İstanbul"));
+        assert_eq!(vec![0..9], find_all("ISTANBUL", "ıstanbul"));
+    }
+
+    #[test]
+    fn greek_sigma() {
+        assert_eq!(vec![0..8], find_all("ΟΔΟΣ", "οδος"));
+        assert_eq!(vec![0..8], find_all("οδοσ", "ΟΔΟΣ"));
+    }
+
+    #[test]
+    fn empty_query_is_everywhere() {
+        let query = CaseFolded::new("");
+        assert_eq!(Some(0..0), query.find_at("Rust", 0));
+        assert_eq!(Some(4..4), query.find_at("Rust", 4));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(!has_uppercase("rust", false));
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase(r"\w+\S", true));
+        assert!(has_uppercase(r"\w+S", true));
+        assert!(has_uppercase(r"\S", false));
+    }
+}
@@ -0,0 +1,439 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    mem,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use memchr::{memchr_iter, memrchr};
+use same_file::Handle;
+
+use crate::{
+    searcher::{self, Progress, Stats},
+    Config, Match, Searcher, Sink,
+};
+
+// How often the files are checked for new lines
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Search the lines appended to the files of the config as they come,
+/// like `tail -f | grep`. Only lines added after we started count.
+///
+/// A file that gets shorter was truncated and is searched again from
+/// the start. Once another file shows up at the path, after a log
+/// rotation say, the rest of the old one is searched and the new one
+/// from its start.
+///
+/// Runs until killed, or until -m lines were selected in every file or
+/// one was with -q. Returns the number of files that couldn't be opened.
+pub fn follow<S: Sink>(searcher: &Searcher, sink: &mut S) -> io::Result<usize> {
+    let config = searcher.config();
+    let mut failed = 0;
+    let mut files = Vec::new();
+    for path in &config.paths {
+        match Followed::open(Path::new(path), config) {
+            Ok(file) => files.push(file),
+            Err(err) => {
+                sink.error(Some(Path::new(path)), &err);
+                failed += 1;
+            }
+        }
+    }
+
+    let mut switched = Switched {
+        current: None,
+        begun: vec![false; files.len()],
+    };
+
+    while !files.is_empty() {
+        for (index, file) in files.iter_mut().enumerate() {
+            let mut sink = Switching {
+                sink,
+                switched: &mut switched,
+                index,
+                path: file.path.clone(),
+            };
+            file.poll(searcher, &mut sink)?;
+        }
+
+        let matched = |file: &Followed| file.stats.matched_lines > 0;
+        let done = if config.quiet {
+            files.iter().any(matched)
+        } else {
+            files.iter().all(|file| file.progress.is_done(config))
+        };
+        if done {
+            end(&files, &mut switched, sink)?;
+            break;
+        }
+
+        thread::sleep(INTERVAL);
+    }
+
+    Ok(failed)
+}
+
+// Every file followed is over, each gets its end
+fn end<S: Sink>(
+    files: &[Followed],
+    switched: &mut Switched,
+    sink: &mut S,
+) -> io::Result<()> {
+    for (index, file) in files.iter().enumerate() {
+        let mut sink = Switching {
+            sink: &mut *sink,
+            switched: &mut *switched,
+            index,
+            path: file.path.clone(),
+        };
+        sink.end(&file.stats)?;
+    }
+    Ok(())
+}
+
+// A file being followed and how far we are into it
+struct Followed {
+    path: PathBuf,
+    reader: BufReader<File>,
+    // The file we have open, to notice when the path is another one
+    handle: Handle,
+    // Bytes read so far, a file shorter than this was truncated
+    pos: u64,
+    // The last line until its line ending shows up
+    partial: Vec<u8>,
+    progress: Progress,
+    stats: Stats,
+}
+
+impl Followed {
+    // Open the file at `path` and skip what's already in it
+    fn open(path: &Path, config: &Config) -> io::Result<Followed> {
+        let mut file = Followed::new(path, File::open(path)?, config)?;
+
+        // Only counted, so the line numbers are right
+        loop {
+            let buf = file.reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            file.progress.line_number += memchr_iter(b'\n', buf).count();
+            match memrchr(b'\n', buf) {
+                Some(end) => {
+                    file.partial.clear();
+                    file.partial.extend_from_slice(&buf[end + 1..]);
+                }
+                None => file.partial.extend_from_slice(buf),
+            }
+
+            let read = buf.len();
+            file.reader.consume(read);
+            file.pos += read as u64;
+        }
+        file.progress.byte_offset = file.pos as usize - file.partial.len();
+
+        Ok(file)
+    }
+
+    fn new(path: &Path, file: File, config: &Config) -> io::Result<Followed> {
+        Ok(Followed {
+            path: path.to_path_buf(),
+            handle: Handle::from_file(file.try_clone()?)?,
+            reader: BufReader::new(file),
+            pos: 0,
+            partial: Vec::new(),
+            progress: Progress::new(config),
+            stats: Stats::default(),
+        })
+    }
+
+    // Search whatever happened to the file since the last time
+    fn poll<S: Sink>(
+        &mut self,
+        searcher: &Searcher,
+        sink: &mut S,
+    ) -> io::Result<()> {
+        let config = searcher.config();
+
+        // Truncated in place, like logrotate's copytruncate does
+        if self.reader.get_ref().metadata()?.len() < self.pos {
+            let mut file = self.reader.get_ref().try_clone()?;
+            file.seek(SeekFrom::Start(0))?;
+            self.restart(file, config)?;
+        }
+
+        let lines = self.read()?;
+        self.search(lines, searcher, sink)?;
+
+        // Moved away and replaced, nothing to do until the new file is
+        // there. Writers may still finish with the old one, so it has
+        // been read to the end just now.
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if Handle::from_file(file.try_clone()?)? == self.handle {
+            return Ok(());
+        }
+
+        // The old file won't get the rest of its last line
+        if !self.partial.is_empty() {
+            let last = mem::take(&mut self.partial);
+            self.search(last, searcher, sink)?;
+        }
+        self.restart(file, config)?;
+        let lines = self.read()?;
+        self.search(lines, searcher, sink)
+    }
+
+    // Go on with `file` from its start, the line numbers too
+    fn restart(&mut self, file: File, config: &Config) -> io::Result<()> {
+        self.handle = Handle::from_file(file.try_clone()?)?;
+        self.reader = BufReader::new(file);
+        self.pos = 0;
+        self.partial.clear();
+        self.progress.restart(config);
+        Ok(())
+    }
+
+    // The lines appended since the last read that are complete
+    fn read(&mut self) -> io::Result<Vec<u8>> {
+        let mut lines = Vec::new();
+        loop {
+            let read = self.reader.read_until(b'\n', &mut self.partial)?;
+            if read == 0 {
+                break;
+            }
+            self.pos += read as u64;
+            if self.partial.ends_with(b"\n") {
+                lines.append(&mut self.partial);
+            }
+        }
+        Ok(lines)
+    }
+
+    fn search<S: Sink>(
+        &mut self,
+        lines: Vec<u8>,
+        searcher: &Searcher,
+        sink: &mut S,
+    ) -> io::Result<()> {
+        self.stats += searcher::search_from(
+            searcher.matcher(),
+            lines.as_slice(),
+            searcher.config(),
+            sink,
+            &mut self.progress,
+        )?;
+        Ok(())
+    }
+}
+
+// Which files the sink has begun, and which one it's on
+struct Switched {
+    current: Option<usize>,
+    begun: Vec<bool>,
+}
+
+// Begins the file the first time something is handed over from it, and
+// resumes it whenever the last thing handed over was from another one,
+// so it's clear where each line comes from
+struct Switching<'a, S> {
+    sink: &'a mut S,
+    switched: &'a mut Switched,
+    index: usize,
+    path: PathBuf,
+}
+
+impl<S: Sink> Switching<'_, S> {
+    fn switch(&mut self) -> io::Result<()> {
+        if self.switched.current == Some(self.index) {
+            return Ok(());
+        }
+        self.switched.current = Some(self.index);
+        match mem::replace(&mut self.switched.begun[self.index], true) {
+            true => self.sink.resume(&self.path),
+            false => self.sink.begin(&self.path),
+        }
+    }
+}
+
+impl<S: Sink> Sink for Switching<'_, S> {
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        self.switch()?;
+        self.sink.matched(m)
+    }
+
+    fn context(&mut self, m: &Match) -> io::Result<()> {
+        self.switch()?;
+        self.sink.context(m)
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        self.sink.context_break()
+    }
+
+    fn binary_matched(&mut self) -> io::Result<()> {
+        self.switch()?;
+        self.sink.binary_matched()
+    }
+
+    fn end(&mut self, stats: &Stats) -> io::Result<()> {
+        self.switch()?;
+        self.sink.end(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use super::*;
+    use crate::printer::Printer;
+
+    fn append(path: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn appended_truncated_and_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "frog 0\nno\nfr").unwrap();
+
+        let config = Config {
+            patterns: vec![String::from("frog")],
+            line_number: true,
+            ..Config::default()
+        };
+        let searcher = Searcher::new(config).unwrap();
+        let mut file = Followed::open(&path, searcher.config()).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, searcher.config(), false);
+        let mut poll = |file: &mut Followed| {
+            file.poll(&searcher, &mut printer).unwrap();
+        };
+
+        // What was there before isn't searched, only finished
+        append(&path, "og 1\nfrog 2\nno");
+        poll(&mut file);
+
+        // Copied away and truncated
+        fs::write(&path, "frog 3\n").unwrap();
+        poll(&mut file);
+
+        // Moved away, then written to some more before the new file
+        let rotated = dir.path().join("app.log.1");
+        fs::rename(&path, &rotated).unwrap();
+        append(&rotated, "frog 4");
+        poll(&mut file);
+        fs::write(&path, "frog 5\n").unwrap();
+        poll(&mut file);
+
+        drop(printer);
+        assert_eq!(
+            "3:frog 1\n4:frog 2\n1:frog 3\n2:frog 4\n1:frog 5\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(5, file.stats.matched_lines);
+    }
+
+    #[test]
+    fn json_begins_and_ends_each_file_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [dir.path().join("a.log"), dir.path().join("b.log")];
+        for path in &paths {
+            fs::write(path, "").unwrap();
+        }
+
+        let config = Config {
+            patterns: vec![String::from("frog")],
+            json: true,
+            ..Config::default()
+        };
+        let searcher = Searcher::new(config).unwrap();
+        let mut files: Vec<Followed> = paths
+            .iter()
+            .map(|path| Followed::open(path, searcher.config()).unwrap())
+            .collect();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, searcher.config(), true);
+        let mut switched = Switched {
+            current: None,
+            begun: vec![false; 2],
+        };
+
+        // a, b, then a again
+        for index in [0, 1, 0] {
+            append(&paths[index], "frog\n");
+            let file = &mut files[index];
+            let mut sink = Switching {
+                sink: &mut printer,
+                switched: &mut switched,
+                index,
+                path: file.path.clone(),
+            };
+            file.poll(&searcher, &mut sink).unwrap();
+        }
+        end(&files, &mut switched, &mut printer).unwrap();
+
+        drop(printer);
+        let events: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let kind = line.split('"').nth(3).unwrap().to_string();
+                let file = if line.contains("a.log") { "a" } else { "b" };
+                format!("{kind} {file}")
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "begin a", "match a", "begin b", "match b", "match a", "end a",
+                "end b"
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn binary_lines_appended() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        fs::write(&path, "").unwrap();
+
+        let config = Config {
+            patterns: vec![String::from("frog")],
+            ..Config::default()
+        };
+        let searcher = Searcher::new(config).unwrap();
+        let mut file = Followed::open(&path, searcher.config()).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, searcher.config(), false);
+        let mut switched = Switched {
+            current: None,
+            begun: vec![false],
+        };
+        let mut sink = Switching {
+            sink: &mut printer,
+            switched: &mut switched,
+            index: 0,
+            path: path.clone(),
+        };
+
+        append(&path, "frog\n\0frog\n");
+        file.poll(&searcher, &mut sink).unwrap();
+        // Nothing more is searched once it's reported as binary
+        append(&path, "frog\n");
+        file.poll(&searcher, &mut sink).unwrap();
+        assert!(file.progress.is_done(searcher.config()));
+
+        drop(printer);
+        let expected = format!("Binary file {} matches\n", path.display());
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use crate::fold::fold;
+
+/// A literal query that also matches with up to `max` typos, where a
+/// typo is a character added, removed or changed (Levenshtein distance).
+///
+/// Uses Sellers' algorithm: the edit distance table of the query against
+/// the line, except that a match may start anywhere in the line for
+/// free. One column of the table is kept and updated per character, so
+/// a line is searched in `query length * line length` steps.
+#[derive(Debug)]
+pub struct Fuzzy {
+    query: Vec<char>,
+    max: usize,
+    ignore_case: bool,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max: usize, ignore_case: bool) -> Fuzzy {
+        Fuzzy {
+            query: query.chars().collect(),
+            max,
+            ignore_case,
+        }
+    }
+
+    /// Byte range of the first hit in `line` starting at or after
+    /// `start`. A hit is extended as long as that brings it closer to
+    /// the query, so "hello" finds all of "hello" and not "hell".
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        let len = self.query.len();
+        // cost[i] is the distance of the first i characters of the query
+        // to the best text ending here, from[i] where that text starts
+        let mut cost: Vec<usize> = (0..=len).collect();
+        let mut from = vec![start; len + 1];
+        let mut best: Option<(usize, Range<usize>)> = None;
+
+        for (i, c) in line[start..].char_indices() {
+            let at = start + i;
+            let end = at + c.len_utf8();
+
+            // The cell up and to the left, from the previous column
+            let (mut diagonal, mut diagonal_from) = (0, at);
+            cost[0] = 0;
+            from[0] = end;
+
+            for q in 1..=len {
+                let changed = !self.same(self.query[q - 1], c);
+                let mut next = (diagonal + usize::from(changed), diagonal_from);
+                // c is extra
+                if cost[q] + 1 < next.0 {
+                    next = (cost[q] + 1, from[q]);
+                }
+                // The query character is missing
+                if cost[q - 1] + 1 < next.0 {
+                    next = (cost[q - 1] + 1, from[q - 1]);
+                }
+
+                (diagonal, diagonal_from) = (cost[q], from[q]);
+                (cost[q], from[q]) = next;
+            }
+
+            let (distance, hit_start) = (cost[len], from[len]);
+            match &best {
+                // Leaving out the whole query isn't a hit
+                None if distance > self.max || hit_start == end => continue,
+                None => {}
+                // Past the end of the hit, or on to the next one
+                Some((_, hit))
+                    if distance > self.max || hit_start != hit.start =>
+                {
+                    break
+                }
+                // Only keep going if it gets closer, or as close but longer
+                Some((best_distance, _)) if distance > *best_distance => {
+                    continue
+                }
+                Some(_) => {}
+            }
+            best = Some((distance, hit_start..end));
+        }
+
+        best.map(|(_, range)| range)
+    }
+
+    /// Whether all of `text` is at most `max` typos away from the query.
+    pub fn is_close(&self, text: &str) -> bool {
+        self.distance(text) <= self.max
+    }
+
+    /// The edit distance between the query and `hit`.
+    pub fn distance(&self, hit: &str) -> usize {
+        let hit: Vec<char> = hit.chars().collect();
+        let mut row: Vec<usize> = (0..=hit.len()).collect();
+
+        for (i, &q) in self.query.iter().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, &c) in hit.iter().enumerate() {
+                let next = (diagonal + usize::from(!self.same(q, c)))
+                    .min(row[j + 1] + 1)
+                    .min(row[j] + 1);
+                diagonal = row[j + 1];
+                row[j + 1] = next;
+            }
+        }
+
+        row[hit.len()]
+    }
+
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && fold(a).eq(fold(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, max: usize, line: &str) -> Option<(usize, String)> {
+        let fuzzy = Fuzzy::new(query, max, false);
+        let range = fuzzy.find_at(line, 0)?;
+        let hit = &line[range];
+        Some((fuzzy.distance(hit), hit.to_string()))
+    }
+
+    #[test]
+    fn typos() {
+        assert_eq!(Some((0, String::from("hello"))), find("hello", 1, "hello"));
+        // Changed, removed and added characters
+        assert_eq!(Some((1, String::from("helo"))), find("hello", 1, "o helo"));
+        assert_eq!(Some((1, String::from("hallo"))), find("hello", 1, "hallo"));
+        assert_eq!(
+            Some((1, String::from("helllo"))),
+            find("hello", 1, "helllo!")
+        );
+        assert_eq!(None, find("hello", 1, "hola"));
+        // Two hits next to each other stay two hits
+        let fuzzy = Fuzzy::new("cat", 1, false);
+        assert_eq!(Some(0..3), fuzzy.find_at("catcat", 0));
+    }
+
+    #[test]
+    fn ranges_are_byte_offsets() {
+        let fuzzy = Fuzzy::new("café", 1, false);
+        assert_eq!(Some(6..10), fuzzy.find_at("été cafe!", 0));
+        assert_eq!(None, fuzzy.find_at("été cafe!", 10));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let fuzzy = Fuzzy::new("Straße", 1, true);
+        assert_eq!(Some(0..6), fuzzy.find_at("STRASE", 0));
+        assert_eq!(1, fuzzy.distance("STRASE"));
+    }
+}
@@ -0,0 +1,140 @@
+//! The `--json` output, one JSON object per line. The format is
+//! documented on [`run`](crate::run).
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    time::Duration,
+};
+
+use crate::{searcher::Stats, Match};
+
+pub fn begin<W: Write>(out: &mut W, path: &str) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"begin","data":{{"path":{}}}}}"#,
+        string(path)
+    )
+}
+
+/// A `match` or a `context` event, depending on `kind`.
+pub fn line<W: Write>(
+    out: &mut W,
+    kind: &str,
+    path: &str,
+    m: &Match,
+) -> io::Result<()> {
+    let submatches: Vec<String> = m
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, range)| {
+            let distance = match m.distances.get(i) {
+                Some(distance) => format!(r#","distance":{distance}"#),
+                None => String::new(),
+            };
+            format!(
+                r#"{{"match":{},"start":{},"end":{}{distance}}}"#,
+                string(&m.line[range.clone()]),
+                range.start,
+                range.end
+            )
+        })
+        .collect();
+
+    let last_line_number = match m.last_line_number() {
+        last if last == m.line_number => String::new(),
+        last => format!(r#","last_line_number":{last}"#),
+    };
+
+    writeln!(
+        out,
+        r#"{{"type":"{kind}","data":{{"path":{},"line_number":{}{last_line_number},"absolute_offset":{},"text":{},"submatches":[{}]}}}}"#,
+        string(path),
+        m.line_number,
+        m.byte_offset,
+        string(&m.line),
+        submatches.join(",")
+    )
+}
+
+pub fn end<W: Write>(out: &mut W, path: &str, stats: &Stats) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"end","data":{{"path":{},"matched_lines":{},"matches":{},"bytes_searched":{}}}}}"#,
+        string(path),
+        stats.matched_lines,
+        stats.matches,
+        stats.bytes_searched
+    )
+}
+
+pub fn summary<W: Write>(
+    out: &mut W,
+    files_searched: usize,
+    files_matched: usize,
+    stats: &Stats,
+    elapsed: Duration,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        r#"{{"type":"summary","data":{{"files_searched":{files_searched},"files_matched":{files_matched},"matched_lines":{},"matches":{},"bytes_searched":{},"elapsed_secs":{}}}}}"#,
+        stats.matched_lines,
+        stats.matches,
+        stats.bytes_searched,
+        elapsed.as_secs_f64()
+    )
+}
+
+// A JSON string literal, quotes included
+fn string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            // Other control characters have no short escape
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(
+            r#""say \"hi\"\\\n\t\u001b[0m é""#,
+            string("say \"hi\"\\\n\t\x1b[0m é")
+        );
+    }
+
+    #[test]
+    fn match_event() {
+        let mut out = Vec::new();
+        let m = Match {
+            line: "fn run() { run() }".into(),
+            line_number: 4,
+            byte_offset: 53,
+            columns: vec![3..6, 11..14],
+            distances: Vec::new(),
+        };
+
+        line(&mut out, "match", "src/lib.rs", &m).unwrap();
+        assert_eq!(
+            r#"{"type":"match","data":{"path":"src/lib.rs","line_number":4,"absolute_offset":53,"text":"fn run() { run() }","submatches":[{"match":"run","start":3,"end":6},{"match":"run","start":11,"end":14}]}}"#.to_string() + "\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -1,67 +1,224 @@
-use std::{env, error::Error, fs};
+use std::{
+    borrow::Cow,
+    env,
+    error::Error,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    num::NonZeroUsize,
+    ops::Range,
+    thread,
+};
 
-pub struct Config {
-    pub query: String,
-    pub file_path: String,
-    pub ignore_case: bool,
-}
+use regex::{Regex, RegexBuilder};
 
+pub use config::{Color, Config, ConfigError, Encoding};
+use fold::CaseFolded;
+pub use matcher::Matcher;
+pub use mmap::{literal_lines, LiteralLines};
+use printer::Printer;
+pub use searcher::{Searcher, SearcherBuilder, Stats};
+pub use sink::Sink;
 
-impl Config {
-    // fn new(args: &[String]) -> Config {
-    //     if args.len() < 3 {
-    //         panic!("not enough arguments");
-    //     }
+mod config;
+mod context;
+mod decompress;
+mod encoding;
+mod fold;
+mod follow;
+mod fuzzy;
+mod json;
+mod matcher;
+mod mmap;
+mod multiline;
+mod parallel;
+mod pool;
+mod printer;
+mod replace;
+mod searcher;
+mod sink;
+mod walk;
 
-    //     let query = args[1].clone();
-    //     let file_path = args[2].clone();
+/// Search as `config` says and print the results to stdout.
+///
+/// Returns whether any line was selected, for the exit status.
+///
+/// # JSON output
+///
+/// With `--json` every line printed is a JSON object with a `type` and a
+/// `data` field:
+///
+/// ```text
+/// {"type":"begin","data":{"path":"src/lib.rs"}}
+/// {"type":"context","data":{"path":"src/lib.rs","line_number":3,
+///     "absolute_offset":40,"text":"use std::io;","submatches":[]}}
+/// {"type":"match","data":{"path":"src/lib.rs","line_number":4,
+///     "absolute_offset":53,"text":"fn run() {",
+///     "submatches":[{"match":"run","start":3,"end":6}]}}
+/// {"type":"end","data":{"path":"src/lib.rs","matched_lines":1,
+///     "matches":1,"bytes_searched":1024}}
+/// {"type":"summary","data":{"files_searched":1,"files_matched":1,
+///     "matched_lines":1,"matches":1,"bytes_searched":1024,
+///     "elapsed_secs":0.002}}
+/// ```
+///
+/// - `begin` and `end` wrap the events of each file searched, `end` has
+///   the numbers for that file. `matches` counts every hit, with `-v`
+///   the selected lines. With `--follow` the events of the files followed
+///   are mixed, each file still has one `begin` and, once following
+///   stops, one `end`.
+/// - `match` is a selected line, `context` a line printed around one
+///   because of `-A`, `-B` or `-C`. With `-v` the selected lines have no
+///   submatches.
+/// - `line_number` starts at 1, `absolute_offset` is the byte offset of
+///   the start of the line in the file.
+/// - `start` and `end` of a submatch are byte offsets into `text`, the
+///   end is exclusive. With `--fuzzy` a submatch also has a `distance`,
+///   the number of typos in it.
+/// - With `-U` a match can span lines, its `text` has the line endings
+///   in between and `last_line_number` says where it ends.
+/// - With `--replace` the `text` of a match has the replacements made
+///   and the submatches are where the replacements are.
+/// - `summary` comes last, once, with the totals of the whole search.
+///
+/// Text that isn't valid UTF-8 has the invalid bytes replaced with
+/// U+FFFD. Standard input has the path `(standard input)`.
+pub fn run(mut config: Config) -> Result<bool, Box<dyn Error>> {
+    // Only color a terminal, and not when NO_COLOR asks us not to
+    if config.color == Color::Auto {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        config.color = if io::stdout().is_terminal() && !no_color {
+            Color::Always
+        } else {
+            Color::Never
+        };
+    }
 
-    //     Config { query, file_path }
-    // }
+    // Compile the pattern first so a bad pattern is reported
+    // before we touch the file
+    let searcher = Searcher::new(config)?;
+    let config = searcher.config();
 
-    // We want to let caller handle the Error, calling panic
-    // is a good choice
-    pub fn build(
-        mut args: impl Iterator<Item = String>,
-    ) -> Result<Config, &'static str> {
-        // Skip file path
-        args.next();
-
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
+    // Rewriting files prints nothing but the diff, if asked for one
+    if config.in_place {
+        let out = &mut io::stdout().lock();
+        return replace::in_place(searcher.matcher(), config, out);
+    }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a file path"),
-        };
+    let quiet = config.quiet;
+    let with_filename = walk::multiple_files(config);
+    let mut printer = Printer::new(io::stdout().lock(), config, with_filename);
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
-        })
+    // A single file gets printed as it's searched, no need to buffer it
+    let searched = if config.follow {
+        follow::follow(&searcher, &mut printer)
+    } else if with_filename && threads > 1 {
+        parallel::search(searcher, &mut printer, threads)
+    } else {
+        searcher.search_files(&mut printer)
+    };
+
+    let searched = searched.and_then(|failed| {
+        printer.finish()?;
+        Ok(failed)
+    });
+
+    let failed = match searched {
+        Ok(failed) => failed,
+        // Whoever reads our output stopped, e.g. `| head`
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+            return Ok(printer.has_matched())
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Like grep, -q only cares about the match and not the errors
+    let matched = printer.has_matched();
+    if failed > 0 && !(quiet && matched) {
+        return Err(format!("{failed} file(s) could not be searched").into());
     }
+
+    Ok(matched)
 }
 
-// the () as Result is ok for a "run" function
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+/// A line and where it was found.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    // Borrowed when searching a string, owned when searching a reader
+    pub line: Cow<'a, str>,
+    // Starts at 1, like editors count lines
+    pub line_number: usize,
+    // Offset of the start of the line from the start of the contents
+    pub byte_offset: usize,
+    // Byte range of each hit within the line
+    pub columns: Vec<Range<usize>>,
+    // Typos in each hit with --fuzzy, empty otherwise
+    pub distances: Vec<usize>,
+}
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
-    } else {
-        search(&config.query, &contents)
-    };
+impl Match<'_> {
+    /// Copy the line so the match can outlive the buffer it was read into.
+    pub fn into_owned(self) -> Match<'static> {
+        Match {
+            line: Cow::Owned(self.line.into_owned()),
+            line_number: self.line_number,
+            byte_offset: self.byte_offset,
+            columns: self.columns,
+            distances: self.distances,
+        }
+    }
 
-    for line in results {
-        println!("{line}");
+    /// Number of the last line, the same as `line_number` unless the
+    /// match spans lines with -U.
+    pub fn last_line_number(&self) -> usize {
+        self.line_number + self.line.matches('\n').count()
     }
+}
 
-    Ok(())
+/// Like `search`, but keeps track of where each match was found.
+pub fn find_matches<'a>(
+    matcher: &Matcher,
+    contents: &'a str,
+) -> Vec<Match<'a>> {
+    lines(matcher, contents)
+        .filter(|m| !m.columns.is_empty())
+        .collect()
+}
+
+// Every line with its number, offset and hits, matching or not
+fn lines<'m, 'a: 'm>(
+    matcher: &'m Matcher,
+    contents: &'a str,
+) -> impl Iterator<Item = Match<'a>> + 'm {
+    let mut byte_offset = 0;
+
+    // Same lines as contents.lines(), but we need the length of the
+    // line ending to keep track of the offset
+    contents
+        .split_inclusive('\n')
+        .enumerate()
+        .map(move |(index, raw)| {
+            let offset = byte_offset;
+            byte_offset += raw.len();
+
+            let line = match raw.strip_suffix('\n') {
+                Some(line) => line.strip_suffix('\r').unwrap_or(line),
+                None => raw,
+            };
+
+            let columns = matcher.find_all(line);
+            Match {
+                line: Cow::Borrowed(line),
+                line_number: index + 1,
+                byte_offset: offset,
+                distances: matcher.distances(line, &columns),
+                columns,
+            }
+        })
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
@@ -95,12 +252,65 @@ pub fn search_case_insensitive<'a>(
     // }
 
     // results
+    // contents
+    //     .lines()
+    //     .filter(|line| line.to_lowercase().contains(&query.to_lowercase()))
+    //     .collect()
+
+    // Lowercasing allocates two strings per line and isn't proper
+    // Unicode case folding anyway (ß vs SS), fold while comparing instead
+    let query = CaseFolded::new(query);
+    contents
+        .lines()
+        .filter(|line| query.find_at(line, 0).is_some())
+        .collect()
+}
+
+/// Compile `query` as a regular expression.
+///
+/// Anchors (`^`, `$`) apply to each line since we search line by line.
+pub fn build_regex(
+    query: &str,
+    ignore_case: bool,
+) -> Result<Regex, PatternError> {
+    RegexBuilder::new(query)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|source| PatternError {
+            pattern: query.to_string(),
+            source,
+        })
+}
+
+pub fn search_regex<'a>(pattern: &Regex, contents: &'a str) -> Vec<&'a str> {
     contents
         .lines()
-        .filter(|line| line.to_lowercase().contains(&query.to_lowercase()))
+        .filter(|line| pattern.is_match(line))
         .collect()
 }
 
+#[derive(Debug)]
+pub struct PatternError {
+    pattern: String,
+    source: regex::Error,
+}
+
+impl Error for PatternError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid regular expression '{}'\n{}",
+            self.pattern, self.source
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,4 +341,75 @@ Trust me.";
             search_case_insensitive(query, contents)
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn regex_anchors_and_classes() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        let pattern = build_regex(r"^[A-Z]\w+:$", false).unwrap();
+
+        assert_eq!(vec!["Rust:"], search_regex(&pattern, contents));
+    }
+
+    #[test]
+    fn regex_alternation_and_groups() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        let pattern = build_regex(r"(pick|trust) (three|me)", true).unwrap();
+
+        assert_eq!(
+            vec!["Pick three.", "Trust me."],
+            search_regex(&pattern, contents)
+        );
+    }
+
+    #[test]
+    fn regex_invalid_pattern() {
+        let err = build_regex("(unclosed", false).unwrap_err();
+
+        assert!(err.to_string().contains("'(unclosed'"));
+    }
+
+    #[test]
+    fn run_reports_invalid_pattern() {
+        let config = Config {
+            patterns: vec![String::from("[a-")],
+            paths: vec![String::from("poem.txt")],
+            regex: true,
+            ..Config::default()
+        };
+
+        assert!(run(config).is_err());
+    }
+
+    #[test]
+    fn match_positions() {
+        let contents = "\
+Rust:\r
+safe, fast, productive.
+Pick three.
+Trust me, trust me.";
+        let config = Config {
+            patterns: vec![String::from("rust")],
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+
+        assert_eq!(
+            vec![Match {
+                line: Cow::Borrowed("Trust me, trust me."),
+                line_number: 4,
+                byte_offset: 43,
+                columns: vec![1..5, 11..15],
+                distances: Vec::new(),
+            }],
+            find_matches(&matcher, contents)
+        );
+    }
+}
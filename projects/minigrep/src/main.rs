@@ -1,19 +1,29 @@
 use std::{env, process};
 
-use minigrep::Config;
+use minigrep::{Config, ConfigError};
 
 fn main() {
     let config = Config::build(env::args()).unwrap_or_else(|err| {
+        // --help and --version go to stdout and aren't failures
+        if let ConfigError::Help | ConfigError::Version = err {
+            println!("{err}");
+            process::exit(0);
+        }
+
         // Print to stderr
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(2);
     });
 
-    println!("Searching for {}", config.query);
-    println!("In file {}", config.file_path);
-    
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    // Like grep, 0 when a line was selected, 1 when none was
+    // and 2 when something went wrong
+    match minigrep::run(config) {
+        Ok(true) => process::exit(0),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("Application error: {e}");
+            process::exit(2);
+        }
     }
 }
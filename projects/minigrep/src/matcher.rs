@@ -0,0 +1,613 @@
+use std::{ops::Range, slice};
+
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use regex::Regex;
+
+use crate::{
+    build_regex,
+    fold::{self, CaseFolded},
+    fuzzy::Fuzzy,
+    Config, PatternError,
+};
+
+/// Decides whether a line matches the query, built once from the
+/// `Config` and reused for every line of every file.
+pub struct Matcher {
+    kind: Kind,
+    // -w, only matches with no word character right before or after
+    word: bool,
+    // -w as part of the regex, with the word itself as group 1
+    words: Option<Regex>,
+    // -x, only a match spanning the whole line
+    whole_line: bool,
+    // Whether --replace templates can refer to capture groups
+    captures: bool,
+    // With --replace and several -E patterns, each of them on its own.
+    // Combined they number their groups in a row, `$1` has to be the
+    // first group of the pattern that matched.
+    replacing: Vec<Regex>,
+}
+
+enum Kind {
+    Literal(String),
+    // Folds as it compares, so the offsets stay those of the line. One
+    // per pattern, Aho-Corasick only folds ASCII.
+    CaseInsensitive(Vec<CaseFolded>),
+    // Several literals searched for in one pass over the line
+    Literals(AhoCorasick),
+    Regex(Regex),
+    // --fuzzy, one per pattern
+    Fuzzy(Vec<Fuzzy>),
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, PatternError> {
+        let patterns = &config.patterns;
+
+        // --smart-case only kicks in when every pattern is lowercase
+        let ignore_case = config.ignore_case
+            || (config.smart_case
+                && !patterns
+                    .iter()
+                    .any(|pattern| fold::has_uppercase(pattern, config.regex)));
+
+        let kind = match (config.fuzzy, patterns.as_slice()) {
+            (Some(typos), _) => Kind::Fuzzy(
+                patterns
+                    .iter()
+                    .map(|pattern| Fuzzy::new(pattern, typos, ignore_case))
+                    .collect(),
+            ),
+            // An empty regex would match everything, no patterns
+            // should match nothing
+            (None, [_, ..]) if config.regex => Kind::Regex(any_regex(
+                patterns,
+                ignore_case,
+                config.line_regexp,
+                config.multiline,
+                false,
+            )?),
+            (None, [pattern]) if ignore_case => {
+                Kind::CaseInsensitive(vec![CaseFolded::new(pattern)])
+            }
+            // Aho-Corasick only folds ASCII
+            (None, _)
+                if ignore_case && !patterns.iter().all(|p| p.is_ascii()) =>
+            {
+                Kind::CaseInsensitive(
+                    patterns.iter().map(|p| CaseFolded::new(p)).collect(),
+                )
+            }
+            (None, [pattern]) => Kind::Literal(pattern.clone()),
+            (None, _) => match literals(patterns, ignore_case) {
+                Some(literals) => Kind::Literals(literals),
+                // Too many for Aho-Corasick, the regex engine may still
+                // manage
+                None => Kind::Regex(any_regex(
+                    &escape(patterns),
+                    ignore_case,
+                    config.line_regexp,
+                    config.multiline,
+                    false,
+                )?),
+            },
+        };
+
+        // The leftmost match failing -w doesn't mean a longer one at the
+        // same spot does too, "a|ab" finds "a" in "ab". The regex has to
+        // look for whole words itself.
+        let words = match kind {
+            Kind::Regex(_) if config.word_regexp && !config.line_regexp => {
+                let escaped;
+                let patterns = match config.regex {
+                    true => patterns,
+                    false => {
+                        escaped = escape(patterns);
+                        &escaped
+                    }
+                };
+                Some(any_regex(
+                    patterns,
+                    ignore_case,
+                    false,
+                    config.multiline,
+                    true,
+                )?)
+            }
+            _ => None,
+        };
+
+        // Literals that ended up a regex have no groups of their own
+        let several =
+            config.regex && config.replace.is_some() && patterns.len() > 1;
+        let replacing = match kind {
+            Kind::Regex(_) if several => patterns
+                .iter()
+                .map(|pattern| {
+                    any_regex(
+                        slice::from_ref(pattern),
+                        ignore_case,
+                        config.line_regexp,
+                        config.multiline,
+                        false,
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Matcher {
+            kind,
+            word: config.word_regexp,
+            words,
+            whole_line: config.line_regexp,
+            captures: config.regex,
+            replacing,
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        if self.word || self.whole_line {
+            return self.find_at(line, 0).is_some();
+        }
+
+        match &self.kind {
+            Kind::Literal(query) => line.contains(query.as_str()),
+            Kind::CaseInsensitive(_) => self.kind.find_at(line, 0).is_some(),
+            Kind::Literals(literals) => literals.is_match(line),
+            Kind::Regex(pattern) => pattern.is_match(line),
+            Kind::Fuzzy(_) => self.kind.find_at(line, 0).is_some(),
+        }
+    }
+
+    /// Byte range of the first match in `line` starting at or after
+    /// `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        if self.whole_line {
+            // The closest hit can be shorter than a line that's close
+            // enough as a whole, "frog" in "frogs"
+            if let Kind::Fuzzy(patterns) = &self.kind {
+                let close = start == 0
+                    && patterns.iter().any(|pattern| pattern.is_close(line));
+                return close.then_some(0..line.len());
+            }
+            // Literals have only one possible match at the start, regexes
+            // are anchored already, so the leftmost match is the one
+            return self
+                .kind
+                .find_at(line, start)
+                .filter(|range| *range == (0..line.len()));
+        }
+        if let Some(words) = &self.words {
+            return find_word(words, line, start);
+        }
+        if !self.word {
+            return self.kind.find_at(line, start);
+        }
+
+        // Literals find the longest match at each spot, if that isn't a
+        // word the shorter ones aren't either
+        let mut at = start;
+        while at <= line.len() {
+            let range = self.kind.find_at(line, at)?;
+            if is_word(line, &range) {
+                return Some(range);
+            }
+            // "cat" in "concat cat", try again right after where
+            // this one started
+            at = range.start
+                + line[range.start..].chars().next().map_or(1, char::len_utf8);
+        }
+        None
+    }
+
+    /// Byte ranges of every non-overlapping match in `line`.
+    pub fn find_all(&self, line: &str) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+
+        while start <= line.len() {
+            let Some(range) = self.find_at(line, start) else {
+                break;
+            };
+
+            // An empty match would find itself forever, step over
+            // the next character
+            start = if range.is_empty() {
+                range.end
+                    + line[range.end..].chars().next().map_or(1, char::len_utf8)
+            } else {
+                range.end
+            };
+            ranges.push(range);
+        }
+
+        ranges
+    }
+
+    /// The query if it's a single plain literal, which can be searched
+    /// for in a whole file rather than line by line. Every line found
+    /// that way still has to go through `find_all`.
+    pub fn literal(&self) -> Option<&str> {
+        match &self.kind {
+            Kind::Literal(query) => Some(query),
+            _ => None,
+        }
+    }
+
+    /// With --fuzzy, how many typos each of `ranges` has, empty
+    /// otherwise.
+    pub fn distances(&self, line: &str, ranges: &[Range<usize>]) -> Vec<usize> {
+        let Kind::Fuzzy(patterns) = &self.kind else {
+            return Vec::new();
+        };
+
+        // The pattern closest to the hit is the one that found it
+        ranges
+            .iter()
+            .map(|range| {
+                let hit = &line[range.clone()];
+                patterns.iter().map(|p| p.distance(hit)).min().unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// `line` with each of `ranges`, as found by `find_all`, replaced by
+    /// `template`, and the ranges of the replacements in the new line.
+    ///
+    /// With -E the template can refer to capture groups as `$1` or
+    /// `${name}`, and `$$` is a plain `$`. Literal patterns have no
+    /// groups, so the template is used as it is.
+    pub fn replace(
+        &self,
+        line: &str,
+        ranges: &[Range<usize>],
+        template: &str,
+    ) -> (String, Vec<Range<usize>>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut columns = Vec::with_capacity(ranges.len());
+        let mut last = 0;
+
+        for range in ranges {
+            replaced.push_str(&line[last..range.start]);
+            let start = replaced.len();
+            match &self.kind {
+                // Searching again from the start of the match finds the
+                // same match, this time with its groups. The first of
+                // several patterns to match it is the one that did.
+                Kind::Regex(pattern) if self.captures => {
+                    let caps = self
+                        .replacing
+                        .iter()
+                        .find_map(|pattern| {
+                            pattern.captures_at(line, range.start).filter(
+                                |caps| caps.get(0).unwrap().range() == *range,
+                            )
+                        })
+                        .or_else(|| pattern.captures_at(line, range.start));
+                    if let Some(caps) = caps {
+                        caps.expand(template, &mut replaced);
+                    }
+                }
+                _ => replaced.push_str(template),
+            }
+            columns.push(start..replaced.len());
+            last = range.end;
+        }
+        replaced.push_str(&line[last..]);
+
+        (replaced, columns)
+    }
+}
+
+impl Kind {
+    fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match self {
+            Kind::Literal(query) => line[start..]
+                .find(query.as_str())
+                .map(|i| start + i..start + i + query.len()),
+            // The leftmost hit of any pattern, the longest of those
+            // starting at the same spot like Aho-Corasick's
+            Kind::CaseInsensitive(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| pattern.find_at(line, start))
+                .min_by_key(|range| (range.start, usize::MAX - range.end)),
+            Kind::Literals(literals) => literals
+                .find(Input::new(line).range(start..))
+                .map(|m| m.range()),
+            Kind::Regex(pattern) => {
+                pattern.find_at(line, start).map(|m| m.range())
+            }
+            // The leftmost hit of any pattern
+            Kind::Fuzzy(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| pattern.find_at(line, start))
+                .min_by_key(|range| range.start),
+        }
+    }
+}
+
+// Whether `range` is a whole word of `line`, with no word character
+// touching it on either side. Word characters are Unicode letters,
+// digits and '_', so "naïve" is one word.
+fn is_word(line: &str, range: &Range<usize>) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    let before = line[..range.start].chars().next_back();
+    let after = line[range.end..].chars().next();
+
+    !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+}
+
+// The word (group 1) of the first match of `words` starting at or after
+// `start`. The non-word character before it may be right before `start`.
+fn find_word(words: &Regex, line: &str, start: usize) -> Option<Range<usize>> {
+    let before = line[..start].chars().next_back();
+    let mut at = start - before.map_or(0, char::len_utf8);
+    loop {
+        let word = words.captures_at(line, at)?.get(1)?.range();
+        if word.start >= start {
+            return Some(word);
+        }
+        // Only ^ gets a word to start before `start`
+        at = start;
+    }
+}
+
+fn escape(patterns: &[String]) -> Vec<String> {
+    patterns
+        .iter()
+        .map(|pattern| regex::escape(pattern))
+        .collect()
+}
+
+// No patterns at all, from an empty -f file, never match. None when
+// there are too many patterns for an automaton.
+fn literals(patterns: &[String], ignore_case: bool) -> Option<AhoCorasick> {
+    AhoCorasick::builder()
+        // Highlight the longest of the patterns starting at the same spot
+        .match_kind(MatchKind::LeftmostLongest)
+        .ascii_case_insensitive(ignore_case)
+        .build(patterns)
+        .ok()
+}
+
+// One regex matching any of the patterns, so lines are still only
+// scanned once. With `whole_line` the patterns are anchored, otherwise
+// "a|ab" would find "a" in "ab" and never see the whole line. With `word`
+// they're between non-word characters, and group 1 is the word.
+fn any_regex(
+    patterns: &[String],
+    ignore_case: bool,
+    whole_line: bool,
+    multiline: bool,
+    word: bool,
+) -> Result<Regex, PatternError> {
+    let any: Vec<String> = patterns
+        .iter()
+        .map(|pattern| format!("(?:{pattern})"))
+        .collect();
+    let any = match (whole_line, word) {
+        (true, _) => format!("^(?:{})$", any.join("|")),
+        (false, true) => format!(r"(?:^|\W)({})(?:\W|$)", any.join("|")),
+        (false, false) => any.join("|"),
+    };
+    // With -U the text is a whole file, ^ and $ still go by the line
+    let any = match multiline {
+        true => format!("(?mR){any}"),
+        false => any,
+    };
+
+    build_regex(&any, ignore_case).map_err(|err| {
+        // Point at the pattern that's wrong rather than the combination
+        patterns
+            .iter()
+            .find_map(|pattern| build_regex(pattern, ignore_case).err())
+            .unwrap_or(err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(query: &str, ignore_case: bool, regex: bool) -> Matcher {
+        let config = Config {
+            patterns: vec![query.to_string()],
+            ignore_case,
+            regex,
+            ..Config::default()
+        };
+        Matcher::new(&config).unwrap()
+    }
+
+    #[test]
+    fn literal_ranges() {
+        let matcher = matcher("ab", false, false);
+
+        assert_eq!(vec![0..2, 4..6], matcher.find_all("abcdab"));
+        assert!(matcher.find_all("ABCD").is_empty());
+    }
+
+    #[test]
+    fn case_insensitive_ranges_use_original_offsets() {
+        // 'İ' lowercases to two characters, the ranges must still
+        // point into the original line
+        let matcher = matcher("rust", true, false);
+
+        assert_eq!(vec![3..7], matcher.find_all("İ RUST"));
+    }
+
+    #[test]
+    fn smart_case() {
+        let config = Config {
+            patterns: vec![String::from("rust")],
+            smart_case: true,
+            ..Config::default()
+        };
+        assert!(Matcher::new(&config).unwrap().is_match("Rust"));
+
+        let config = Config {
+            patterns: vec![String::from("Rust")],
+            ..config
+        };
+        assert!(!Matcher::new(&config).unwrap().is_match("rust"));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let mut config = Config {
+            patterns: vec![String::from("frog"), String::from("fr")],
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        assert!(matches!(matcher.kind, Kind::Literals(_)));
+        assert_eq!(vec![0..4, 6..8], matcher.find_all("frog, fry"));
+
+        config.ignore_case = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..4], matcher.find_all("FROG"));
+
+        // Folded all the way when Aho-Corasick can't do it
+        config.patterns = vec![String::from("STRASSE"), String::from("é")];
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..7, 8..10], matcher.find_all("Straße É"));
+
+        config.patterns = vec![String::from("^b"), String::from("g$")];
+        config.regex = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..1, 3..4], matcher.find_all("bbog"));
+
+        // Nothing matches without patterns
+        config.patterns.clear();
+        assert!(!Matcher::new(&config).unwrap().is_match("frog"));
+    }
+
+    #[test]
+    fn whole_words() {
+        let mut config = Config {
+            patterns: vec![String::from("cat")],
+            word_regexp: true,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(
+            vec![0..3, 16..19],
+            matcher.find_all("cat concat cat_ cat.")
+        );
+        // Letters with accents are word characters too
+        assert!(!matcher.is_match("écat"));
+
+        config.patterns = vec![String::from("-v")];
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![3..5], matcher.find_all("ls -v"));
+
+        // The longer alternative is a word when the leftmost isn't
+        config.patterns = vec![String::from("a|ab")];
+        config.regex = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..2], matcher.find_all("ab"));
+        assert_eq!(vec![0..2, 3..4, 5..6], matcher.find_all("ab a-a"));
+        config.patterns = vec![String::from("foo|foobar")];
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..6], matcher.find_all("foobar baz"));
+
+        // Same for literals Aho-Corasick can't fold
+        config.patterns = vec![String::from("é"), String::from("éa")];
+        config.regex = false;
+        config.ignore_case = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..3], matcher.find_all("ÉA"));
+    }
+
+    #[test]
+    fn whole_lines() {
+        let mut config = Config {
+            patterns: vec![String::from("a"), String::from("ab")],
+            line_regexp: true,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        assert!(matcher.is_match("ab"));
+        assert!(!matcher.is_match("abc"));
+
+        config.regex = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec![0..2], matcher.find_all("ab"));
+        assert!(!matcher.is_match("cab"));
+
+        // Aho-Corasick can't fold "é", the longest pattern found there
+        // is the whole line all the same
+        config.patterns = vec![String::from("é"), String::from("éa")];
+        config.regex = false;
+        config.ignore_case = true;
+        let matcher = Matcher::new(&config).unwrap();
+        assert!(matches!(matcher.kind, Kind::CaseInsensitive(_)));
+        assert!(matcher.is_match("ÉA"));
+    }
+
+    #[test]
+    fn replace_with_groups() {
+        let line = "fn run(), fn main()";
+
+        let groups = matcher(r"fn (\w+)", false, true);
+        let ranges = groups.find_all(line);
+        assert_eq!(
+            (String::from("run: $1(), main: $1()"), vec![0..7, 11..19]),
+            groups.replace(line, &ranges, "$1: $$1")
+        );
+
+        // Each pattern has its own groups
+        let config = Config {
+            patterns: vec![String::from("(a)"), String::from("(b)")],
+            regex: true,
+            replace: Some(String::from("[$1]")),
+            ..Config::default()
+        };
+        let several = Matcher::new(&config).unwrap();
+        let ranges = several.find_all("abc");
+        assert_eq!(
+            String::from("[a][b]c"),
+            several.replace("abc", &ranges, "[$1]").0
+        );
+
+        let literal = matcher("fn", false, false);
+        let ranges = literal.find_all(line);
+        assert_eq!(
+            String::from("$1 run(), $1 main()"),
+            literal.replace(line, &ranges, "$1").0
+        );
+    }
+
+    #[test]
+    fn fuzzy() {
+        let config = Config {
+            patterns: vec![String::from("receive"), String::from("error")],
+            fuzzy: Some(1),
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        let line = "recive an eror, receive";
+
+        let ranges = matcher.find_all(line);
+        assert_eq!(vec![0..6, 10..14, 16..23], ranges);
+        assert_eq!(vec![1, 1, 0], matcher.distances(line, &ranges));
+
+        // With -x it's the whole line that has to be close
+        let config = Config {
+            line_regexp: true,
+            ..config
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        let ranges = matcher.find_all("errors");
+        assert_eq!(vec![0..6], ranges);
+        assert_eq!(vec![1], matcher.distances("errors", &ranges));
+        assert!(!matcher.is_match("an error"));
+    }
+
+    #[test]
+    fn empty_matches_advance() {
+        let matcher = matcher("x*", false, true);
+
+        assert_eq!(vec![0..0, 1..2, 2..2], matcher.find_all("ax"));
+    }
+}
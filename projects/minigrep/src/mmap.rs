@@ -0,0 +1,379 @@
+use std::{borrow::Cow, fs::File, io, ops::Range};
+
+use memchr::{memchr, memchr_iter, memmem::Finder, memrchr};
+use memmap2::Mmap;
+
+use crate::{
+    multiline,
+    searcher::{self, Stats},
+    Config, Encoding, Matcher, Sink,
+};
+
+// Below this reading is about as fast, and mapping has a cost of its own
+const MIN_LEN: u64 = 1 << 20;
+
+/// Map `file` into memory if it's worth it, a regular file of at least
+/// a MiB that isn't compressed.
+pub fn map(
+    file: &File,
+    config: &Config,
+    decompress: bool,
+) -> io::Result<Option<Mmap>> {
+    let metadata = file.metadata()?;
+    if config.no_mmap || decompress || !metadata.is_file() {
+        return Ok(None);
+    }
+    if metadata.len() < MIN_LEN {
+        return Ok(None);
+    }
+
+    // SAFETY: the map is only read. If another process truncates the
+    // file while we search it we get a SIGBUS, that's the price of
+    // mapping and why there's --no-mmap.
+    let map = unsafe { Mmap::map(file)? };
+    Ok(Some(map))
+}
+
+/// Search a whole file that is in memory.
+///
+/// A plain literal query, without context or -v, jumps straight from
+/// one hit to the next with a vectorised substring search. The lines in
+/// between are only counted, never decoded or matched. Anything else is
+/// searched line by line like a stream.
+pub fn search_slice<S: Sink>(
+    matcher: &Matcher,
+    haystack: &[u8],
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let plain = config.before_context == 0
+        && config.after_context == 0
+        && !config.invert_match;
+
+    // Like the streaming search, the BOM isn't part of the text
+    let utf8 = is_utf8(haystack, config)
+        .then(|| haystack.strip_prefix(b"\xef\xbb\xbf").unwrap_or(haystack));
+
+    match (matcher.literal(), utf8) {
+        // Valid UTF-8 is searched right in the map, without a copy
+        (_, Some(haystack)) if config.multiline => {
+            let text = String::from_utf8_lossy(haystack);
+            multiline::search(matcher, &text, config, sink)
+        }
+        (Some(literal), Some(haystack)) if plain => {
+            search_literal(matcher, literal, haystack, config, sink)
+        }
+        _ => searcher::search_decoded(matcher, haystack, false, config, sink),
+    }
+}
+
+// Whether the file can be searched as it is, no UTF-16 to transcode
+fn is_utf8(haystack: &[u8], config: &Config) -> bool {
+    match config.encoding {
+        Encoding::Utf8 => true,
+        Encoding::Auto => {
+            !(haystack.starts_with(b"\xff\xfe")
+                || haystack.starts_with(b"\xfe\xff"))
+        }
+        _ => false,
+    }
+}
+
+fn search_literal<S: Sink>(
+    matcher: &Matcher,
+    literal: &str,
+    haystack: &[u8],
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let lines_wanted = config.prints_lines();
+    let max_count = searcher::max_count(config);
+    let reached_max =
+        |matched_lines| max_count.is_some_and(|max| matched_lines >= max);
+    let check_binary = lines_wanted && !config.text && !config.json;
+
+    let mut matched_lines = 0;
+    let mut matches = 0;
+    let mut bytes_searched = haystack.len();
+    // Where we've looked for NUL bytes up to, the head of the file is
+    // looked at as soon as there's a hit like in a stream
+    let mut checked = 0;
+    let head = haystack.len().min(searcher::BINARY_HEAD);
+
+    for (line_number, range) in literal_lines(literal, haystack) {
+        // Only with -m 0, otherwise the line reaching -m stops the search
+        if reached_max(matched_lines) {
+            bytes_searched = 0;
+            break;
+        }
+
+        let line = String::from_utf8_lossy(&haystack[range.clone()]);
+        // The hit was a candidate, -w or -x still have their say
+        let columns = matcher.find_all(&line);
+        if columns.is_empty() {
+            continue;
+        }
+
+        matched_lines += 1;
+        matches += columns.len();
+        // Like a stream, stopping early has read the line ending too
+        let line_end = match &haystack[range.end..] {
+            [b'\r', b'\n', ..] => range.end + 2,
+            [b'\n', ..] => range.end + 1,
+            _ => range.end,
+        };
+        let end = range.end.max(head);
+        if check_binary && memchr(0, &haystack[checked..end]).is_some() {
+            sink.binary_matched()?;
+            bytes_searched = line_end;
+            break;
+        }
+        checked = end;
+
+        if lines_wanted {
+            let m = searcher::selected(
+                matcher,
+                config,
+                Cow::Borrowed(&line),
+                line_number,
+                range.start,
+                columns,
+            );
+            sink.matched(&m)?;
+        }
+        if reached_max(matched_lines) {
+            bytes_searched = line_end;
+            break;
+        }
+    }
+
+    Ok(Stats {
+        matched_lines,
+        matches,
+        bytes_searched,
+    })
+}
+
+/// Lines of `haystack` containing `needle`, found by searching the whole
+/// of `haystack` for the needle rather than each line.
+pub fn literal_lines<'h>(needle: &str, haystack: &'h [u8]) -> LiteralLines<'h> {
+    LiteralLines {
+        finder: Finder::new(needle).into_owned(),
+        haystack,
+        pos: 0,
+        newlines: 0,
+        counted: 0,
+    }
+}
+
+/// Iterator returned by [`literal_lines`], with the number of each line
+/// and its byte range without the line ending.
+pub struct LiteralLines<'h> {
+    finder: Finder<'static>,
+    haystack: &'h [u8],
+    // Where to look for the next hit
+    pos: usize,
+    // Line endings before `counted`
+    newlines: usize,
+    counted: usize,
+}
+
+impl Iterator for LiteralLines<'_> {
+    type Item = (usize, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // A file ending in "\n" has no empty line after it
+            if self.pos >= self.haystack.len() {
+                return None;
+            }
+
+            let hit =
+                self.pos + self.finder.find(&self.haystack[self.pos..])?;
+            let start =
+                memrchr(b'\n', &self.haystack[..hit]).map_or(0, |i| i + 1);
+            let end = memchr(b'\n', &self.haystack[hit..])
+                .map_or(self.haystack.len(), |i| hit + i);
+
+            // Only "\r\n" is a line ending, a last "\r" is part of the line
+            let crlf = end < self.haystack.len()
+                && self.haystack[start..end].ends_with(b"\r");
+            let text_end = if crlf { end - 1 } else { end };
+
+            // A hit running into the line ending isn't in the line, the
+            // rest of the line may still have one
+            if hit + self.finder.needle().len() > text_end {
+                self.pos = hit + 1;
+                continue;
+            }
+
+            // The lines skipped only need counting
+            self.newlines +=
+                memchr_iter(b'\n', &self.haystack[self.counted..start]).count();
+            self.counted = start;
+            self.pos = end + 1;
+
+            return Some((self.newlines + 1, start..text_end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::printer::Printer;
+
+    #[test]
+    fn lines_and_numbers() {
+        let haystack = b"a frog\r\nno\nno\nfrog frog\nfro";
+        let lines: Vec<_> = literal_lines("frog", haystack).collect();
+
+        assert_eq!(vec![(1, 0..6), (4, 14..23)], lines);
+    }
+
+    #[test]
+    fn empty_needle_matches_every_line() {
+        let lines: Vec<_> = literal_lines("", b"a\n\nb\n").collect();
+
+        assert_eq!(vec![(1, 0..1), (2, 2..2), (3, 3..4)], lines);
+    }
+
+    #[test]
+    fn hits_stay_inside_the_line() {
+        // "o\r" runs into the first line ending, the last "\r" is text
+        let haystack = b"no\r\nno\r";
+        let lines: Vec<_> = literal_lines("o\r", haystack).collect();
+        assert_eq!(vec![(2, 4..7)], lines);
+
+        assert_eq!(0, literal_lines("o\n", b"no\nno\n").count());
+    }
+
+    #[test]
+    fn same_as_streaming() {
+        let mut haystack = String::new();
+        for i in 0..1000 {
+            haystack.push_str(&format!("line {i} {}\n", i % 7));
+        }
+        let config = Config {
+            patterns: vec![String::from("3")],
+            line_number: true,
+            byte_offset: true,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+
+        let mut mapped = Vec::new();
+        let mut printer = Printer::new(&mut mapped, &config, false);
+        let stats =
+            search_slice(&matcher, haystack.as_bytes(), &config, &mut printer)
+                .unwrap();
+
+        let mut streamed = Vec::new();
+        let mut printer = Printer::new(&mut streamed, &config, false);
+        let expected = searcher::search_reader(
+            &matcher,
+            haystack.as_bytes(),
+            &config,
+            &mut printer,
+        )
+        .unwrap();
+
+        assert_eq!(expected, stats);
+        assert_eq!(String::from_utf8(streamed), String::from_utf8(mapped));
+    }
+
+    #[test]
+    fn stops_where_streaming_does() {
+        let haystack = b"no
+frog 1
+no
+frog 2
+no
+frog 3
+";
+        let configs = [
+            Config {
+                max_count: Some(2),
+                ..Config::default()
+            },
+            Config {
+                max_count: Some(0),
+                ..Config::default()
+            },
+            Config {
+                files_with_matches: true,
+                ..Config::default()
+            },
+        ];
+
+        for config in configs {
+            let config = Config {
+                patterns: vec![String::from("frog")],
+                ..config
+            };
+            let matcher = Matcher::new(&config).unwrap();
+            let mut sink = Printer::new(io::sink(), &config, false);
+            let stats =
+                search_slice(&matcher, haystack, &config, &mut sink).unwrap();
+            let expected = searcher::search_reader(
+                &matcher,
+                &haystack[..],
+                &config,
+                &mut sink,
+            )
+            .unwrap();
+            assert_eq!(expected, stats);
+        }
+    }
+
+    #[test]
+    fn binary_the_same_as_streaming() {
+        // A NUL far from the start only counts once it's searched
+        let mut far = b"frog one\n".to_vec();
+        far.resize(2 * searcher::BINARY_HEAD, b'\n');
+        far.extend_from_slice(b"\0 frog two\n");
+        // One at the start makes even the first hit binary
+        let mut near = b"frog one\n\0\n".to_vec();
+        near.resize(2 * searcher::BINARY_HEAD, b'\n');
+
+        // The literal jumps between hits, the regex goes line by line
+        for (pattern, regex) in [("frog", false), ("fr.g", true)] {
+            let config = Config {
+                patterns: vec![String::from(pattern)],
+                regex,
+                ..Config::default()
+            };
+            let matcher = Matcher::new(&config).unwrap();
+
+            for (haystack, expected) in [
+                (&far, "frog one\nBinary file big.log matches\n"),
+                (&near, "Binary file big.log matches\n"),
+            ] {
+                let mut mapped = Vec::new();
+                let mut printer = Printer::new(&mut mapped, &config, false);
+                printer.begin(Path::new("big.log")).unwrap();
+                let stats =
+                    search_slice(&matcher, haystack, &config, &mut printer)
+                        .unwrap();
+
+                let mut streamed = Vec::new();
+                let mut printer = Printer::new(&mut streamed, &config, false);
+                printer.begin(Path::new("big.log")).unwrap();
+                let reader = io::BufReader::new(haystack.as_slice());
+                let expected_stats = searcher::search_reader(
+                    &matcher,
+                    reader,
+                    &config,
+                    &mut printer,
+                )
+                .unwrap();
+
+                assert_eq!(expected_stats, stats);
+                assert_eq!(expected, String::from_utf8(mapped).unwrap());
+                assert_eq!(expected, String::from_utf8(streamed).unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::{borrow::Cow, io, ops::Range};
+
+use crate::{
+    context::Context,
+    searcher::{self, Stats},
+    Config, Match, Matcher, Sink,
+};
+
+/// Search all of `text` at once for -U, so a hit can span lines.
+///
+/// Hits are handed over with the whole lines they touch, the text of a
+/// `Match` keeps the line endings in between. Hits sharing a line are
+/// one `Match`. Lines in between are context like they'd be otherwise.
+pub fn search<S: Sink>(
+    matcher: &Matcher,
+    text: &str,
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let mut context = Context::new(config.before_context, config.after_context);
+    let mut groups = groups(matcher, text).into_iter().peekable();
+    let mut line_number = 0;
+    let mut matched_lines = 0;
+    let mut matches = 0;
+
+    let lines_wanted = config.prints_lines();
+    let max_count = searcher::max_count(config);
+    let reached_max =
+        |matched_lines| max_count.is_some_and(|max| matched_lines >= max);
+    let binary =
+        lines_wanted && !config.text && !config.json && text.contains('\0');
+
+    // Offset of the line we're at
+    let mut pos = 0;
+    while pos < text.len() {
+        if reached_max(matched_lines) && !context.wants_more() {
+            break;
+        }
+
+        let group = groups.next_if(|(lines, _)| lines.start == pos);
+        if let Some((lines, columns)) = group {
+            if !reached_max(matched_lines) {
+                let raw = trim(&text[lines.clone()]);
+                let count = raw.matches('\n').count() + 1;
+                matched_lines += count;
+                matches += columns.len();
+                if binary {
+                    sink.binary_matched()?;
+                    break;
+                }
+
+                if lines_wanted {
+                    let m = searcher::selected(
+                        matcher,
+                        config,
+                        Cow::Borrowed(raw),
+                        line_number + 1,
+                        pos,
+                        columns,
+                    );
+                    context.selected(m, sink)?;
+                }
+                line_number += count;
+                pos = lines.end;
+                continue;
+            }
+        }
+
+        // A line of its own, the lines of a hit past -m are too
+        let end = text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1);
+        line_number += 1;
+        if lines_wanted {
+            let m = Match {
+                line: Cow::Borrowed(trim(&text[pos..end])),
+                line_number,
+                byte_offset: pos,
+                columns: Vec::new(),
+                distances: Vec::new(),
+            };
+            context.other(m, sink)?;
+        }
+        pos = end;
+    }
+
+    Ok(Stats {
+        matched_lines,
+        matches,
+        bytes_searched: pos,
+    })
+}
+
+// The hits in `text` grouped by the lines they touch, each group with
+// the range of its lines and the hits relative to the start of them
+fn groups(
+    matcher: &Matcher,
+    text: &str,
+) -> Vec<(Range<usize>, Vec<Range<usize>>)> {
+    let mut groups: Vec<(Range<usize>, Vec<Range<usize>>)> = Vec::new();
+
+    for hit in matcher.find_all(text) {
+        let start = text[..hit.start].rfind('\n').map_or(0, |i| i + 1);
+        // The line of the last character of the hit, a hit ending with
+        // a line ending doesn't touch the next line
+        let last = text[..hit.end]
+            .strip_suffix('\n')
+            .map_or(hit.end, str::len)
+            .max(hit.start);
+        let end = text[last..].find('\n').map_or(text.len(), |i| last + i + 1);
+
+        // A hit on a line of the last group is part of that group
+        if groups.last().is_none_or(|(lines, _)| start >= lines.end) {
+            groups.push((start..end, Vec::new()));
+        }
+        let (lines, columns) = groups.last_mut().unwrap();
+        columns.push(hit.start - lines.start..hit.end - lines.start);
+        lines.end = lines.end.max(end);
+    }
+
+    groups
+}
+
+// Without the line ending of the last line
+fn trim(lines: &str) -> &str {
+    match lines.strip_suffix('\n') {
+        Some(lines) => lines.strip_suffix('\r').unwrap_or(lines),
+        None => lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::Printer;
+
+    fn search(text: &str, config: &Config) -> (usize, String) {
+        let matcher = Matcher::new(config).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, config, false);
+
+        let stats =
+            super::search(&matcher, text, config, &mut printer).unwrap();
+        (stats.matched_lines, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn hits_across_lines() {
+        let config = Config {
+            patterns: vec![String::from(r"fn \w+\(\s*a")],
+            regex: true,
+            multiline: true,
+            line_number: true,
+            ..Config::default()
+        };
+        let text = "use a;\nfn run(\n    a: u32,\n) {}\nfn main(a) {}\n";
+
+        assert_eq!(
+            (
+                3,
+                String::from("2-3:fn run(\n    a: u32,\n5:fn main(a) {}\n")
+            ),
+            search(text, &config)
+        );
+    }
+
+    #[test]
+    fn hits_sharing_lines_are_one_match() {
+        let config = Config {
+            patterns: vec![String::from("b\nc"), String::from("d")],
+            multiline: true,
+            before_context: 1,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+
+        assert_eq!(
+            vec![(2..7, vec![0..3, 3..4])],
+            groups(&matcher, "a\nb\ncd\ne")
+        );
+        assert_eq!(
+            (2, String::from("a\nb\ncd\n")),
+            search("a\nb\ncd\ne", &config)
+        );
+    }
+}
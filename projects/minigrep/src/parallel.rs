@@ -0,0 +1,182 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use crate::{pool::ThreadPool, printer::Printer, walk, Config, Searcher, Sink};
+
+// What a worker sends back for each file, the index is the position
+// of the file in the walk
+type Searched = (usize, PathBuf, io::Result<Printer<Vec<u8>>>);
+
+// Files queued per thread at most, the walk waits for the search when
+// it gets that far ahead
+const QUEUED_PER_THREAD: usize = 4;
+
+/// Search the files on `threads` threads.
+///
+/// Each file is searched into its own buffer which is then printed in
+/// one go, so the output of different files never interleaves. With
+/// `--sort-files` the buffers are printed in the order the files were
+/// walked, otherwise as soon as they are done.
+///
+/// Returns the number of files that couldn't be searched.
+pub fn search<W: Write>(
+    searcher: Searcher,
+    printer: &mut Printer<W>,
+    threads: usize,
+) -> io::Result<usize> {
+    let searcher = Arc::new(searcher);
+    let config = searcher.config();
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel::<Searched>();
+
+    thread::scope(|scope| {
+        let walker = scope.spawn(|| {
+            let mut pool =
+                ThreadPool::new(threads).bounded(threads * QUEUED_PER_THREAD);
+            let mut failed = 0;
+            let mut index = 0;
+
+            for path in walk::files(config) {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let path = match path {
+                    Ok(path) => path,
+                    Err(err) => {
+                        eprintln!("{err}");
+                        failed += 1;
+                        continue;
+                    }
+                };
+
+                let searcher = Arc::clone(&searcher);
+                let stop = Arc::clone(&stop);
+                let tx = tx.clone();
+                pool.execute(move || {
+                    // Files queued before printing stopped are skipped
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let config = searcher.config();
+                    let mut printer = Printer::new(Vec::new(), config, true);
+                    let result = searcher
+                        .search_path(&path, &mut printer)
+                        .map(|_| printer);
+                    // Nobody is listening anymore if printing failed
+                    let _ = tx.send((index, path, result));
+                });
+                index += 1;
+            }
+
+            // Dropping the pool waits for the queued files, skipped if
+            // printing stopped, the receiver is done once the last
+            // sender is gone
+            drop(pool);
+            drop(tx);
+            failed
+        });
+
+        // Once printing is over, whether done, failed or stopped early
+        // by -q, there is no point walking further
+        let printed = print(rx, printer, config);
+        stop.store(true, Ordering::Relaxed);
+
+        let walk_failed = walker.join().unwrap();
+        printed.map(|failed| failed + walk_failed)
+    })
+}
+
+fn print<W: Write>(
+    rx: mpsc::Receiver<Searched>,
+    printer: &mut Printer<W>,
+    config: &Config,
+) -> io::Result<usize> {
+    let mut failed = 0;
+
+    // Files finished out of order wait here until it's their turn
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+
+    for (index, path, result) in rx {
+        if config.sort_files {
+            pending.insert(index, (path, result));
+            while let Some((path, result)) = pending.remove(&next) {
+                print_one(printer, &mut failed, path, result)?;
+                next += 1;
+            }
+        } else {
+            print_one(printer, &mut failed, path, result)?;
+        }
+
+        // With -q the first match is all we wanted to know
+        if config.quiet && printer.has_matched() {
+            break;
+        }
+    }
+
+    Ok(failed)
+}
+
+fn print_one<W: Write>(
+    printer: &mut Printer<W>,
+    failed: &mut usize,
+    path: PathBuf,
+    result: io::Result<Printer<Vec<u8>>>,
+) -> io::Result<()> {
+    match result {
+        Ok(file) => printer.append(file),
+        Err(err) => {
+            printer.error(Some(&path), &err);
+            *failed += 1;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn sorted_output_is_contiguous_per_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["c", "a", "d", "b"] {
+            let contents = format!("{name} one\nskip\n{name} two\n");
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+
+        let config = Config {
+            patterns: vec![String::from(" t")],
+            paths: vec![dir.path().to_string_lossy().into_owned()],
+            sort_files: true,
+            after_context: 1,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, false);
+        let searcher = Searcher::new(config).unwrap();
+
+        let failed = search(searcher, &mut printer, 3).unwrap();
+        assert_eq!(0, failed);
+
+        let expected: String = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                format!("{}:{name} two\n", path.display())
+            })
+            .collect::<Vec<_>>()
+            .join("--\n");
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+/// Same idea as the `ThreadPool` from `projects/hello`, but instead of
+/// one channel shared by everyone each worker has its own queue.
+/// A worker with nothing left to do steals from the back of the others'
+/// queues, so one slow file doesn't hold up the jobs queued behind it.
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    shared: Arc<Shared>,
+    // Queue the next job goes to
+    next: usize,
+    // Jobs queued at most before `execute` waits
+    limit: usize,
+}
+
+// A closure that fits thread
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    state: Mutex<State>,
+    // Notified when a job is queued or the pool shuts down
+    signal: Condvar,
+    // Notified when a job is taken, there's room for another one
+    taken: Condvar,
+}
+
+struct State {
+    // Jobs queued but not taken by a worker yet
+    queued: usize,
+    shutdown: bool,
+}
+
+impl ThreadPool {
+    /// Create a new ThreadPool.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the size is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0);
+
+        let shared = Arc::new(Shared {
+            queues: (0..size).map(|_| Mutex::new(VecDeque::new())).collect(),
+            state: Mutex::new(State {
+                queued: 0,
+                shutdown: false,
+            }),
+            signal: Condvar::new(),
+            taken: Condvar::new(),
+        });
+
+        let workers = (0..size)
+            .map(|id| Worker::new(id, Arc::clone(&shared)))
+            .collect();
+
+        ThreadPool {
+            workers,
+            shared,
+            next: 0,
+            limit: usize::MAX,
+        }
+    }
+
+    /// Make `execute` wait for the workers to catch up while `limit`
+    /// jobs are queued, so whoever queues them can't run far ahead.
+    ///
+    /// # Panics
+    ///
+    /// The `bounded` function will panic if the limit is zero.
+    pub fn bounded(mut self, limit: usize) -> ThreadPool {
+        assert!(limit > 0);
+        self.limit = limit;
+        self
+    }
+
+    pub fn execute<F>(&mut self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // Count it first, a worker may take it as soon as it's queued
+        let mut state = self.shared.state.lock().unwrap();
+        while state.queued >= self.limit {
+            state = self.shared.taken.wait(state).unwrap();
+        }
+        state.queued += 1;
+        drop(state);
+
+        // Hand the jobs out in turn, stealing evens it out later
+        self.shared.queues[self.next]
+            .lock()
+            .unwrap()
+            .push_back(Box::new(f));
+        self.next = (self.next + 1) % self.workers.len();
+
+        self.shared.signal.notify_one();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Workers finish whatever is still queued before they exit
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.signal.notify_all();
+
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                thread.join().unwrap();
+            }
+        }
+    }
+}
+
+impl Shared {
+    // Own queue first, oldest job first, then the newest job of
+    // someone else
+    fn take(&self, id: usize) -> Option<Job> {
+        let len = self.queues.len();
+        // Own queue unlocked before stealing, two workers stealing from
+        // each other would otherwise wait on each other forever
+        let own = self.queues[id].lock().unwrap().pop_front();
+        let job = own.or_else(|| {
+            (1..len).find_map(|i| {
+                self.queues[(id + i) % len].lock().unwrap().pop_back()
+            })
+        })?;
+
+        self.state.lock().unwrap().queued -= 1;
+        self.taken.notify_one();
+        Some(job)
+    }
+}
+
+struct Worker {
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    fn new(id: usize, shared: Arc<Shared>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            if let Some(job) = shared.take(id) {
+                job();
+                continue;
+            }
+
+            // Nothing to take, sleep until there is
+            let mut state = shared.state.lock().unwrap();
+            while state.queued == 0 && !state.shutdown {
+                state = shared.signal.wait(state).unwrap();
+            }
+            if state.queued == 0 && state.shutdown {
+                break;
+            }
+        });
+
+        Worker {
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn runs_every_job_before_shutting_down() {
+        let (tx, rx) = mpsc::channel();
+
+        let mut pool = ThreadPool::new(3);
+        for i in 0..50 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        drop(pool);
+        drop(tx);
+
+        let mut done: Vec<i32> = rx.iter().collect();
+        done.sort();
+        assert_eq!((0..50).collect::<Vec<_>>(), done);
+    }
+
+    #[test]
+    fn idle_workers_steal() {
+        let (tx, rx) = mpsc::channel();
+
+        // Whichever worker gets the slow job, the other one runs
+        // everything else, stealing once its own queue is empty
+        let mut pool = ThreadPool::new(2);
+        for i in 0..6 {
+            let tx = tx.clone();
+            pool.execute(move || {
+                if i == 0 {
+                    thread::sleep(Duration::from_millis(200));
+                }
+                tx.send(i).unwrap();
+            });
+        }
+        drop(pool);
+        drop(tx);
+
+        let done: Vec<i32> = rx.iter().collect();
+        assert_eq!(Some(&0), done.last());
+    }
+
+    #[test]
+    fn bounded_waits_for_the_workers() {
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut pool = ThreadPool::new(1).bounded(2);
+            pool.execute(move || _ = gate_rx.recv());
+            for i in 1..4 {
+                pool.execute(|| {});
+                tx.send(i).unwrap();
+            }
+        });
+
+        // The only worker is stuck on the first job, so the third one
+        // has to wait until it's done
+        assert_eq!(Ok(1), rx.recv_timeout(Duration::from_secs(10)));
+        assert_eq!(Ok(2), rx.recv_timeout(Duration::from_secs(10)));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        gate_tx.send(()).unwrap();
+        assert_eq!(Ok(3), rx.recv_timeout(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn workers_stealing_from_each_other_dont_deadlock() {
+        let (tx, rx) = mpsc::channel();
+
+        // Jobs this short keep the workers going idle and stealing
+        thread::spawn(move || {
+            for _ in 0..200 {
+                let mut pool = ThreadPool::new(8);
+                for _ in 0..200 {
+                    pool.execute(|| {});
+                }
+                drop(pool);
+            }
+            tx.send(()).unwrap();
+        });
+
+        // Deadlocked workers would never finish, don't wait forever
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("the workers deadlocked");
+    }
+}
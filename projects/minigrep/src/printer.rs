@@ -0,0 +1,397 @@
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use crate::{config::Color, json, searcher::Stats, walk, Config, Match, Sink};
+
+// ANSI escape codes, the same colors grep uses
+const PATH: &str = "\x1b[35m";
+const NUMBER: &str = "\x1b[32m";
+const SEPARATOR: &str = "\x1b[36m";
+const MATCH: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes the selected lines in the usual grep format,
+/// `path:line:column:offset:text` with each prefix being optional.
+/// Context lines use `-` instead of `:`.
+///
+/// With `--json` every line is written as a JSON event instead, see the
+/// `json` module for the format.
+pub struct Printer<W: Write> {
+    out: W,
+    // The file being searched
+    path: String,
+    with_filename: bool,
+    line_number: bool,
+    column: bool,
+    byte_offset: bool,
+    count: bool,
+    count_matches: bool,
+    stats: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    quiet: bool,
+    json: bool,
+    // Color::Auto was decided by run already
+    color: bool,
+    // Whether any line was printed yet, and for the current file
+    printed: bool,
+    file_printed: bool,
+    // Whether the output of two files needs a "--" in between
+    separate_files: bool,
+    // For the summary at the end
+    files_searched: usize,
+    files_matched: usize,
+    total: Stats,
+    started: Instant,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, config: &Config, with_filename: bool) -> Printer<W> {
+        Printer {
+            out,
+            path: String::new(),
+            with_filename,
+            // --column doesn't make much sense without the line
+            line_number: config.line_number || config.column,
+            column: config.column,
+            byte_offset: config.byte_offset,
+            count: config.count,
+            count_matches: config.count_matches,
+            stats: config.stats,
+            files_with_matches: config.files_with_matches,
+            files_without_match: config.files_without_match,
+            quiet: config.quiet,
+            json: config.json,
+            color: config.color == Color::Always && !config.json,
+            printed: false,
+            file_printed: false,
+            separate_files: config.before_context > 0
+                || config.after_context > 0,
+            files_searched: 0,
+            files_matched: 0,
+            total: Stats::default(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether any file searched so far had a selected line.
+    pub fn has_matched(&self) -> bool {
+        self.files_matched > 0
+    }
+    /// Done with every file.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.json && !self.quiet {
+            json::summary(
+                &mut self.out,
+                self.files_searched,
+                self.files_matched,
+                &self.total,
+                self.started.elapsed(),
+            )?;
+        } else if self.stats && !self.quiet {
+            self.write_stats()?;
+        }
+        self.out.flush()
+    }
+
+    /// Print the output of a file that was searched into its own buffer.
+    pub fn append(&mut self, file: Printer<Vec<u8>>) -> io::Result<()> {
+        if self.separate_files && self.printed && file.printed {
+            self.separator()?;
+        }
+        self.printed |= file.printed;
+        self.files_searched += file.files_searched;
+        self.files_matched += file.files_matched;
+        self.total += file.total;
+        self.out.write_all(&file.out)
+    }
+
+    // Goes between groups of lines that aren't next to each other
+    fn separator(&mut self) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        self.paint(SEPARATOR, "--")?;
+        writeln!(self.out)
+    }
+
+    fn write_line(&mut self, m: &Match, sep: &str) -> io::Result<()> {
+        // Context of different files is never next to each other
+        if self.separate_files && self.printed && !self.file_printed {
+            self.separator()?;
+        }
+        self.printed = true;
+        self.file_printed = true;
+        if self.with_filename {
+            self.paint_path()?;
+            self.paint(SEPARATOR, sep)?;
+        }
+        if self.line_number {
+            // The lines a match spans with -U, like 3-5
+            let last = m.last_line_number();
+            let number = match last == m.line_number {
+                true => m.line_number.to_string(),
+                false => format!("{}-{last}", m.line_number),
+            };
+            self.paint(NUMBER, &number)?;
+            self.paint(SEPARATOR, sep)?;
+        }
+        // Inverted and context lines have no hit to point at
+        if let (true, Some(first)) = (self.column, m.columns.first()) {
+            self.paint(NUMBER, &(first.start + 1).to_string())?;
+            self.paint(SEPARATOR, sep)?;
+        }
+        if self.byte_offset {
+            self.paint(NUMBER, &m.byte_offset.to_string())?;
+            self.paint(SEPARATOR, sep)?;
+        }
+        // Like --column, but the typos of every hit, in order
+        if !m.distances.is_empty() {
+            let distances: Vec<String> =
+                m.distances.iter().map(usize::to_string).collect();
+            self.paint(NUMBER, &format!("~{}", distances.join(",")))?;
+            self.paint(SEPARATOR, sep)?;
+        }
+
+        // Highlight each hit, the text in between stays as it is
+        let mut last = 0;
+        for range in &m.columns {
+            write!(self.out, "{}", &m.line[last..range.start])?;
+            self.paint(MATCH, &m.line[range.clone()])?;
+            last = range.end;
+        }
+        writeln!(self.out, "{}", &m.line[last..])
+    }
+
+    // --stats, apart from the results
+    fn write_stats(&mut self) -> io::Result<()> {
+        if self.printed || self.count || self.files_with_matches {
+            writeln!(self.out)?;
+        }
+        writeln!(
+            self.out,
+            "{} matches\n\
+             {} matched lines\n\
+             {} files contained matches\n\
+             {} files searched\n\
+             {} bytes searched\n\
+             {:.6} seconds",
+            self.total.matches,
+            self.total.matched_lines,
+            self.files_matched,
+            self.files_searched,
+            self.total.bytes_searched,
+            self.started.elapsed().as_secs_f64()
+        )
+    }
+
+    fn paint_path(&mut self) -> io::Result<()> {
+        if self.color {
+            write!(self.out, "{PATH}{}{RESET}", self.path)
+        } else {
+            write!(self.out, "{}", self.path)
+        }
+    }
+
+    // Write `text` in `color`, if colors are on
+    fn paint(&mut self, color: &str, text: &str) -> io::Result<()> {
+        if self.color && !text.is_empty() {
+            write!(self.out, "{color}{text}{RESET}")
+        } else {
+            write!(self.out, "{text}")
+        }
+    }
+}
+
+impl<W: Write> Sink for Printer<W> {
+    fn begin(&mut self, path: &Path) -> io::Result<()> {
+        self.path = if path.as_os_str() == walk::STDIN {
+            String::from("(standard input)")
+        } else {
+            path.display().to_string()
+        };
+        self.file_printed = false;
+
+        if self.json && !self.quiet {
+            json::begin(&mut self.out, &self.path)?;
+        }
+        Ok(())
+    }
+
+    // Every JSON event has its path, one begin per file is enough
+    fn resume(&mut self, path: &Path) -> io::Result<()> {
+        if self.json {
+            self.path = path.display().to_string();
+            return Ok(());
+        }
+        self.begin(path)
+    }
+
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        if self.json {
+            return json::line(&mut self.out, "match", &self.path, m);
+        }
+        self.write_line(m, ":")
+    }
+
+    fn context(&mut self, m: &Match) -> io::Result<()> {
+        if self.json {
+            return json::line(&mut self.out, "context", &self.path, m);
+        }
+        self.write_line(m, "-")
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        self.separator()
+    }
+
+    // Instead of the lines of a binary file
+    fn binary_matched(&mut self) -> io::Result<()> {
+        self.printed = true;
+        writeln!(self.out, "Binary file {} matches", self.path)
+    }
+
+    fn end(&mut self, stats: &Stats) -> io::Result<()> {
+        self.files_searched += 1;
+        if stats.matched_lines > 0 {
+            self.files_matched += 1;
+        }
+        self.total += *stats;
+
+        if self.quiet {
+            return Ok(());
+        }
+        if self.json {
+            return json::end(&mut self.out, &self.path, stats);
+        }
+
+        let matched = stats.matched_lines > 0;
+        if (self.files_with_matches && matched)
+            || (self.files_without_match && !matched)
+        {
+            self.paint_path()?;
+            writeln!(self.out)?;
+        } else if self.count {
+            if self.with_filename {
+                self.paint_path()?;
+                self.paint(SEPARATOR, ":")?;
+            }
+            let count = match self.count_matches {
+                true => stats.matches,
+                false => stats.matched_lines,
+            };
+            writeln!(self.out, "{count}")?;
+        }
+        Ok(())
+    }
+
+    fn error(&mut self, path: Option<&Path>, err: &dyn Error) {
+        match path {
+            Some(path) => eprintln!("{}: {err}", path.display()),
+            None => eprintln!("{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_matches_and_prefixes() {
+        let config = Config {
+            line_number: true,
+            color: Color::Always,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, true);
+        let m = Match {
+            line: "a frog, a bog".into(),
+            line_number: 7,
+            byte_offset: 0,
+            columns: vec![2..6, 10..13],
+            distances: Vec::new(),
+        };
+
+        printer.begin(Path::new("poem.txt")).unwrap();
+        printer.matched(&m).unwrap();
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m7\x1b[0m\x1b[36m:\x1b[0m\
+             a \x1b[1;31mfrog\x1b[0m, a \x1b[1;31mbog\x1b[0m\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn stats_at_the_end() {
+        let config = Config {
+            stats: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, false);
+        let stats = Stats {
+            matched_lines: 2,
+            matches: 3,
+            bytes_searched: 100,
+        };
+
+        for (path, stats) in [("a", stats), ("b", Stats::default())] {
+            printer.begin(Path::new(path)).unwrap();
+            printer.end(&stats).unwrap();
+        }
+        printer.finish().unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(
+            "3 matches\n2 matched lines\n1 files contained matches\n\
+             2 files searched\n100 bytes searched\n"
+        ));
+        assert!(out.ends_with(" seconds\n"));
+    }
+
+    #[test]
+    fn no_escapes_without_color() {
+        let config = Config {
+            color: Color::Never,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, false);
+        let m = Match {
+            line: "a frog".into(),
+            line_number: 1,
+            byte_offset: 0,
+            columns: vec![2..6, 6..6],
+            distances: Vec::new(),
+        };
+
+        printer.matched(&m).unwrap();
+        assert_eq!("a frog\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn typos_of_every_hit() {
+        let config = Config {
+            line_number: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, false);
+        let m = Match {
+            line: "recive an error".into(),
+            line_number: 3,
+            byte_offset: 0,
+            columns: vec![0..6, 10..15],
+            distances: vec![1, 0],
+        };
+
+        printer.matched(&m).unwrap();
+        assert_eq!("3:~1,0:recive an error\n", String::from_utf8(out).unwrap());
+    }
+}
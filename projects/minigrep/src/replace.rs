@@ -0,0 +1,278 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process,
+};
+
+use crate::{decompress::Format, walk, Config, Matcher};
+
+// A line --replace changed
+struct Change<'a> {
+    line_number: usize,
+    old: &'a str,
+    new: String,
+}
+
+/// Make the `--replace` replacements in every file, or with `--dry-run`
+/// only print them to `out` as a unified diff.
+///
+/// Returns whether any line matched, for the exit status.
+pub fn in_place<W: Write>(
+    matcher: &Matcher,
+    config: &Config,
+    out: &mut W,
+) -> Result<bool, Box<dyn Error>> {
+    let mut matched = false;
+    let mut failed = 0;
+
+    for path in walk::files(config) {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("{err}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        match rewrite(matcher, &path, config, out) {
+            Ok(matched_lines) => matched |= matched_lines > 0,
+            // Whoever reads the diff stopped, e.g. `| head`
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                return Ok(matched)
+            }
+            Err(err) => {
+                eprintln!("{}: {err}", path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    match out.flush() {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(err.into())
+        }
+        _ => {}
+    }
+    if failed > 0 {
+        return Err(format!("{failed} file(s) could not be rewritten").into());
+    }
+    Ok(matched)
+}
+
+// Replace the matches in one file, returns the number of lines matched
+fn rewrite<W: Write>(
+    matcher: &Matcher,
+    path: &Path,
+    config: &Config,
+    out: &mut W,
+) -> io::Result<usize> {
+    if path.as_os_str() == walk::STDIN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "standard input can't be rewritten",
+        ));
+    }
+
+    // Binary and compressed files are left alone, replacing in them
+    // would break them. With --text invalid UTF-8 is an error rather
+    // than replaced with U+FFFD.
+    let contents = fs::read(path)?;
+    if Format::detect(&contents).is_some() {
+        return Ok(0);
+    }
+    let contents = match String::from_utf8(contents) {
+        Ok(contents) if config.text || !contents.contains('\0') => contents,
+        Err(err) if config.text => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err))
+        }
+        _ => return Ok(0),
+    };
+    let template = config.replace.as_deref().unwrap_or_default();
+
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut changes = Vec::new();
+    let mut matched_lines = 0;
+
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        // Keep the line ending as it was, "\n" or "\r\n"
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+
+        // -m limits the lines replaced
+        let ranges = match config.max_count {
+            Some(max) if matched_lines >= max => Vec::new(),
+            _ => matcher.find_all(text),
+        };
+        if ranges.is_empty() {
+            rewritten.push_str(line);
+            continue;
+        }
+
+        matched_lines += 1;
+        let (new, _) = matcher.replace(text, &ranges, template);
+        rewritten.push_str(&new);
+        rewritten.push_str(&line[text.len()..]);
+        if new != text {
+            changes.push(Change {
+                line_number: i + 1,
+                old: text,
+                new,
+            });
+        }
+    }
+
+    if config.dry_run {
+        diff(out, path, &changes)?;
+    } else if !changes.is_empty() {
+        write_atomically(path, &rewritten)?;
+    }
+    Ok(matched_lines)
+}
+
+// Print the changes as a unified diff without context lines, which
+// `patch` or `git apply` can apply
+fn diff<W: Write>(
+    out: &mut W,
+    path: &Path,
+    changes: &[Change],
+) -> io::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "--- {}", path.display())?;
+    writeln!(out, "+++ {}", path.display())?;
+    // Changed lines next to each other make one hunk
+    for hunk in changes.chunk_by(|a, b| b.line_number == a.line_number + 1) {
+        let (start, len) = (hunk[0].line_number, hunk.len());
+        writeln!(out, "@@ -{start},{len} +{start},{len} @@")?;
+        for change in hunk {
+            writeln!(out, "-{}", change.old)?;
+        }
+        for change in hunk {
+            writeln!(out, "+{}", change.new)?;
+        }
+    }
+    Ok(())
+}
+
+// Write to a temporary file next to the original and rename it over
+// the original, so the file is either all old or all new even if we
+// get killed halfway
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    // Rewrite what a symlink points to, not the link itself
+    let path = fs::canonicalize(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp =
+        path.with_file_name(format!(".{name}.minigrep-{}", process::id()));
+
+    let written = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(fs::metadata(&path)?.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path, dry_run: bool) -> Config {
+        Config {
+            patterns: vec![String::from("cat")],
+            paths: vec![dir.to_string_lossy().into_owned()],
+            replace: Some(String::from("dog")),
+            in_place: true,
+            dry_run,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn rewrites_files_keeping_line_endings() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pets");
+        fs::write(&file, "a cat\r\nno\ncat cat").unwrap();
+
+        let config = config(dir.path(), false);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut out = Vec::new();
+
+        assert!(in_place(&matcher, &config, &mut out).unwrap());
+        assert_eq!("a dog\r\nno\ndog dog", fs::read_to_string(&file).unwrap());
+        assert!(out.is_empty());
+        // Only the file itself, the temporary one is gone
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn leaves_binary_and_compressed_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            ("nul", &b"cat\0bin\n"[..]),
+            ("png", b"\x89PNG\0cat\n"),
+            ("gz", b"\x1f\x8b\x08\0cat\n"),
+        ];
+        for (name, contents) in files {
+            fs::write(dir.path().join(name), contents).unwrap();
+        }
+        fs::write(dir.path().join("text"), "cat\n").unwrap();
+
+        let mut config = config(dir.path(), false);
+        let matcher = Matcher::new(&config).unwrap();
+        assert!(in_place(&matcher, &config, &mut Vec::new()).unwrap());
+        for (name, contents) in files {
+            assert_eq!(contents, fs::read(dir.path().join(name)).unwrap());
+        }
+        assert_eq!(
+            "dog\n",
+            fs::read_to_string(dir.path().join("text")).unwrap()
+        );
+
+        // Unless it's text after all, as long as it's UTF-8
+        config.text = true;
+        assert!(in_place(&matcher, &config, &mut Vec::new()).is_err());
+        assert_eq!(
+            b"dog\0bin\n",
+            &fs::read(dir.path().join("nul")).unwrap()[..]
+        );
+        assert_eq!(files[1].1, fs::read(dir.path().join("png")).unwrap());
+    }
+
+    #[test]
+    fn dry_run_prints_a_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("pets");
+        fs::write(&file, "cat\ncats\nno\ncat\n").unwrap();
+
+        let config = config(&file, true);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut out = Vec::new();
+
+        assert!(in_place(&matcher, &config, &mut out).unwrap());
+        assert_eq!("cat\ncats\nno\ncat\n", fs::read_to_string(&file).unwrap());
+
+        let path = file.display();
+        assert_eq!(
+            format!(
+                "--- {path}\n+++ {path}\n\
+                 @@ -1,2 +1,2 @@\n-cat\n-cats\n+dog\n+dogs\n\
+                 @@ -4,1 +4,1 @@\n-cat\n+dog\n"
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
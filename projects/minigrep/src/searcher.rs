@@ -0,0 +1,727 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    ops::{AddAssign, Range},
+    path::Path,
+};
+
+use crate::{
+    context::Context, decompress, encoding, mmap, multiline, walk, Config,
+    Encoding, Match, Matcher, PatternError, Sink,
+};
+
+// How much of the start of a file is looked at for a NUL before its
+// first line, past that only the lines searched are. The same for every
+// reader, a mapped file would otherwise be checked all at once.
+pub const BINARY_HEAD: usize = 8192;
+
+/// The search without the printing, for embedding minigrep.
+///
+/// Built from a `Config`, or with [`Searcher::builder`], the pattern is
+/// compiled once and every search hands what it finds to a [`Sink`].
+pub struct Searcher {
+    matcher: Matcher,
+    config: Config,
+}
+
+impl Searcher {
+    pub fn new(config: Config) -> Result<Searcher, PatternError> {
+        let matcher = Matcher::new(&config)?;
+        Ok(Searcher { matcher, config })
+    }
+
+    pub fn builder() -> SearcherBuilder {
+        SearcherBuilder::default()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        &self.matcher
+    }
+
+    /// Search every file under the paths of the config, one after the
+    /// other. A file that can't be searched goes to `Sink::error` and
+    /// the rest are still searched.
+    ///
+    /// Returns the number of files that couldn't be searched.
+    pub fn search_files<S: Sink>(&self, sink: &mut S) -> io::Result<usize> {
+        let mut failed = 0;
+        for path in walk::files(&self.config) {
+            let path = match path {
+                Ok(path) => path,
+                Err(err) => {
+                    sink.error(None, &err);
+                    failed += 1;
+                    continue;
+                }
+            };
+
+            match self.search_path(&path, sink) {
+                // With -q the first match is all we wanted to know
+                Ok(stats) if self.config.quiet && stats.matched_lines > 0 => {
+                    break
+                }
+                Ok(_) => {}
+                // The sink failed, not the file
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    sink.error(Some(&path), &err);
+                    failed += 1;
+                }
+            }
+        }
+
+        Ok(failed)
+    }
+
+    /// Search the file at `path`, or standard input for `-`, between
+    /// `Sink::begin` and `Sink::end`.
+    pub fn search_path<S: Sink>(
+        &self,
+        path: &Path,
+        sink: &mut S,
+    ) -> io::Result<Stats> {
+        search_path(&self.matcher, path, &self.config, sink)
+    }
+
+    /// Search `reader`, transcoded like a file would be. It's up to the
+    /// caller to begin and end it.
+    pub fn search_reader<R: BufRead, S: Sink>(
+        &self,
+        reader: R,
+        sink: &mut S,
+    ) -> io::Result<Stats> {
+        search_decoded(&self.matcher, reader, false, &self.config, sink)
+    }
+
+    /// Search `haystack` all in memory, like a memory-mapped file. It's
+    /// up to the caller to begin and end it.
+    pub fn search_slice<S: Sink>(
+        &self,
+        haystack: &[u8],
+        sink: &mut S,
+    ) -> io::Result<Stats> {
+        mmap::search_slice(&self.matcher, haystack, &self.config, sink)
+    }
+}
+
+/// Builds a [`Searcher`] from the options that matter to embedding,
+/// the printing ones are left out. Anything else can still be set on a
+/// `Config` for [`Searcher::new`].
+#[derive(Debug, Default)]
+pub struct SearcherBuilder {
+    config: Config,
+}
+
+impl SearcherBuilder {
+    /// A line is selected if any of the patterns matches.
+    pub fn pattern(mut self, pattern: impl Into<String>) -> SearcherBuilder {
+        self.config.patterns.push(pattern.into());
+        self
+    }
+
+    /// A file or directory for `search_files`, `-` for standard input.
+    pub fn path(mut self, path: impl Into<String>) -> SearcherBuilder {
+        self.config.paths.push(path.into());
+        self
+    }
+
+    pub fn regex(mut self, yes: bool) -> SearcherBuilder {
+        self.config.regex = yes;
+        self
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> SearcherBuilder {
+        self.config.ignore_case = yes;
+        self
+    }
+
+    pub fn smart_case(mut self, yes: bool) -> SearcherBuilder {
+        self.config.smart_case = yes;
+        self
+    }
+
+    pub fn word_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.config.word_regexp = yes;
+        self
+    }
+
+    pub fn line_regexp(mut self, yes: bool) -> SearcherBuilder {
+        self.config.line_regexp = yes;
+        self
+    }
+
+    pub fn invert_match(mut self, yes: bool) -> SearcherBuilder {
+        self.config.invert_match = yes;
+        self
+    }
+
+    /// Allow up to `max` typos per hit.
+    pub fn fuzzy(mut self, max: Option<usize>) -> SearcherBuilder {
+        self.config.fuzzy = max;
+        self
+    }
+
+    /// Lines of context before and after each selected line.
+    pub fn context(mut self, before: usize, after: usize) -> SearcherBuilder {
+        self.config.before_context = before;
+        self.config.after_context = after;
+        self
+    }
+
+    /// Stop each file after `max` selected lines.
+    pub fn max_count(mut self, max: Option<usize>) -> SearcherBuilder {
+        self.config.max_count = max;
+        self
+    }
+
+    /// Hand over the lines with the hits replaced by `template`.
+    pub fn replace(mut self, template: Option<String>) -> SearcherBuilder {
+        self.config.replace = template;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> SearcherBuilder {
+        self.config.encoding = encoding;
+        self
+    }
+
+    /// Hand over the selected lines of binary files too.
+    pub fn text(mut self, yes: bool) -> SearcherBuilder {
+        self.config.text = yes;
+        self
+    }
+
+    pub fn build(self) -> Result<Searcher, PatternError> {
+        Searcher::new(self.config)
+    }
+}
+
+/// What searching a file found, or all of them added up.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    // Lines selected, so the ones not matching with -v
+    pub matched_lines: usize,
+    // Hits in the selected lines, with -v the lines themselves. Might
+    // only be the lines when Config::counts_matches is false.
+    pub matches: usize,
+    pub bytes_searched: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+        self.bytes_searched += other.bytes_searched;
+    }
+}
+
+/// Search the file at `path`, or standard input for `-`.
+///
+/// Compressed files are searched decompressed if their name ends in
+/// `.gz`, `.zst` or `.bz2`, or whatever their name with -z. Line numbers
+/// and offsets are those of the decompressed text.
+pub fn search_path<S: Sink>(
+    matcher: &Matcher,
+    path: &Path,
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let decompress = config.search_zip || decompress::has_extension(path);
+
+    let stats = if path.as_os_str() == walk::STDIN {
+        sink.begin(path)?;
+        let stdin = io::stdin().lock();
+        search_decoded(matcher, stdin, decompress, config, sink)?
+    } else {
+        // Open it first, a file we can't read was never begun
+        let file = File::open(path)?;
+        let map = mmap::map(&file, config, decompress)?;
+        sink.begin(path)?;
+        match map {
+            Some(map) => mmap::search_slice(matcher, &map, config, sink)?,
+            None => {
+                let file = BufReader::new(file);
+                search_decoded(matcher, file, decompress, config, sink)?
+            }
+        }
+    };
+
+    sink.end(&stats)?;
+    Ok(stats)
+}
+
+/// Search `reader` decompressed, if asked to, and transcoded to UTF-8.
+pub fn search_decoded<R: BufRead, S: Sink>(
+    matcher: &Matcher,
+    reader: R,
+    decompress: bool,
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let reader: Box<dyn BufRead> = match decompress {
+        true => decompress::reader(reader)?,
+        false => Box::new(reader),
+    };
+    let mut reader = encoding::reader(reader, config.encoding)?;
+    if config.multiline {
+        let mut text = Vec::new();
+        reader.read_to_end(&mut text)?;
+        let text = String::from_utf8_lossy(&text);
+        return multiline::search(matcher, &text, config, sink);
+    }
+    search_reader(matcher, reader, config, sink)
+}
+
+/// Search `reader` line by line, handing every selected line and its
+/// context to `sink`.
+///
+/// Only one line is held in memory at a time (plus the `-B` context),
+/// so this works on pipes and on files of any size. Bytes that aren't
+/// valid UTF-8 are replaced with U+FFFD rather than failing the search.
+///
+/// Text doesn't have NUL bytes, so once one shows up the file is taken
+/// as binary and its first match is only reported with a "Binary file
+/// matches" line, unless there's --text.
+pub fn search_reader<R: BufRead, S: Sink>(
+    matcher: &Matcher,
+    reader: R,
+    config: &Config,
+    sink: &mut S,
+) -> io::Result<Stats> {
+    let mut progress = Progress::new(config);
+    search_from(matcher, reader, config, sink, &mut progress)
+}
+
+/// How far [`search_from`] got into a file, to go on from there with
+/// more of it. Lines are numbered from the start of the file, and -m
+/// and the context carry over from one piece to the next.
+pub struct Progress {
+    /// Lines searched or skipped so far
+    pub line_number: usize,
+    /// Bytes of those lines
+    pub byte_offset: usize,
+    matched_lines: usize,
+    // A NUL showed up, further matches are only reported as binary
+    binary: bool,
+    // The binary match was reported, the rest isn't searched
+    finished: bool,
+    context: Context,
+}
+
+impl Progress {
+    pub fn new(config: &Config) -> Progress {
+        Progress {
+            line_number: 0,
+            byte_offset: 0,
+            matched_lines: 0,
+            binary: false,
+            finished: false,
+            context: Context::new(config.before_context, config.after_context),
+        }
+    }
+
+    /// Start over with another file, the lines selected so far still
+    /// count towards -m.
+    pub fn restart(&mut self, config: &Config) {
+        *self = Progress {
+            matched_lines: self.matched_lines,
+            ..Progress::new(config)
+        };
+    }
+
+    /// Nothing more of the file would be selected or printed, past -m
+    /// with no context owed to the last match.
+    pub fn is_done(&self, config: &Config) -> bool {
+        let reached_max =
+            max_count(config).is_some_and(|max| self.matched_lines >= max);
+        self.finished || (reached_max && !self.context.wants_more())
+    }
+}
+
+/// Like [`search_reader`], but `reader` goes on where `progress` left
+/// off, for a file that is searched a piece at a time. The pieces have
+/// to end with whole lines.
+///
+/// Returns the stats of this piece only.
+pub fn search_from<R: BufRead, S: Sink>(
+    matcher: &Matcher,
+    mut reader: R,
+    config: &Config,
+    sink: &mut S,
+    progress: &mut Progress,
+) -> io::Result<Stats> {
+    let mut buf = Vec::new();
+    let start = progress.byte_offset;
+    let mut matched_lines = 0;
+    let mut matches = 0;
+
+    let lines_wanted = config.prints_lines();
+    let max_count = max_count(config);
+    let reached_max = |progress: &Progress| {
+        max_count.is_some_and(|max| progress.matched_lines >= max)
+    };
+
+    // Only printing the lines of a binary file would mess up a terminal,
+    // JSON escapes them anyway. Usually there's a NUL near the start.
+    let check_binary = lines_wanted && !config.text && !config.json;
+    if check_binary && progress.byte_offset == 0 {
+        let head = reader.fill_buf()?;
+        progress.binary |= head[..head.len().min(BINARY_HEAD)].contains(&0);
+    }
+
+    loop {
+        // Past -m the rest of the file doesn't matter, apart from the
+        // context still owed to the last match
+        if progress.is_done(config) {
+            break;
+        }
+
+        // The buffer is reused, it only ever grows to the longest line
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        progress.line_number += 1;
+        let line_number = progress.line_number;
+        let offset = progress.byte_offset;
+        progress.byte_offset += read;
+        progress.binary |= check_binary && buf.contains(&0);
+
+        let line = trim_line_ending(&buf);
+        let line = String::from_utf8_lossy(line);
+
+        // Counting doesn't need to know where the hits are, unless it's
+        // the hits that are counted
+        if !lines_wanted {
+            let hits = match config.counts_matches() {
+                true => matcher.find_all(&line).len(),
+                false => usize::from(matcher.is_match(&line)),
+            };
+            if (hits > 0) != config.invert_match {
+                progress.matched_lines += 1;
+                matched_lines += 1;
+                matches += hits.max(1);
+            }
+            continue;
+        }
+
+        let columns = matcher.find_all(&line);
+        // -v flips which lines are selected
+        let selected = columns.is_empty() == config.invert_match;
+        if progress.binary && selected {
+            if !reached_max(progress) {
+                progress.matched_lines += 1;
+                matched_lines += 1;
+                matches += columns.len().max(1);
+                sink.binary_matched()?;
+            }
+            progress.finished = true;
+            break;
+        }
+
+        if selected && !reached_max(progress) {
+            progress.matched_lines += 1;
+            matched_lines += 1;
+            matches += columns.len().max(1);
+            let m = self::selected(
+                matcher,
+                config,
+                line,
+                line_number,
+                offset,
+                columns,
+            );
+            progress.context.selected(m, sink)?;
+        } else {
+            let m = Match {
+                distances: matcher.distances(&line, &columns),
+                line,
+                line_number,
+                byte_offset: offset,
+                columns,
+            };
+            progress.context.other(m, sink)?;
+        }
+    }
+
+    Ok(Stats {
+        matched_lines,
+        matches,
+        bytes_searched: progress.byte_offset - start,
+    })
+}
+
+/// The most lines to select per file. -l, -L and -q only need to know
+/// whether there is a match at all.
+pub fn max_count(config: &Config) -> Option<usize> {
+    if config.prints_lines() || config.count {
+        config.max_count
+    } else {
+        Some(1)
+    }
+}
+
+/// A selected line with its hits, after --replace if there's one.
+pub fn selected<'a>(
+    matcher: &Matcher,
+    config: &Config,
+    line: Cow<'a, str>,
+    line_number: usize,
+    byte_offset: usize,
+    columns: Vec<Range<usize>>,
+) -> Match<'a> {
+    let distances = matcher.distances(&line, &columns);
+    let (line, columns) = match &config.replace {
+        Some(template) => {
+            let (line, columns) = matcher.replace(&line, &columns, template);
+            (Cow::Owned(line), columns)
+        }
+        None => (line, columns),
+    };
+
+    Match {
+        line,
+        line_number,
+        byte_offset,
+        columns,
+        distances,
+    }
+}
+
+/// Strip "\n" or "\r\n", like str::lines does.
+pub fn trim_line_ending(line: &[u8]) -> &[u8] {
+    // A "\r" of its own at the end is part of the line, like str::lines
+    match line.strip_suffix(b"\n") {
+        Some(line) => line.strip_suffix(b"\r").unwrap_or(line),
+        None => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::printer::Printer;
+
+    fn search(contents: &[u8], config: &Config) -> (usize, String) {
+        let matcher = Matcher::new(config).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, config, false);
+
+        let stats =
+            search_reader(&matcher, contents, config, &mut printer).unwrap();
+        (stats.matched_lines, String::from_utf8(out).unwrap())
+    }
+
+    // Writes down every call, the way an embedding crate might
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Sink for Events {
+        fn begin(&mut self, path: &Path) -> io::Result<()> {
+            let name = path.file_name().unwrap().to_string_lossy();
+            self.0.push(format!("begin {name}"));
+            Ok(())
+        }
+
+        fn matched(&mut self, m: &Match) -> io::Result<()> {
+            self.0.push(format!("{}:{}", m.line_number, m.line));
+            Ok(())
+        }
+
+        fn context(&mut self, m: &Match) -> io::Result<()> {
+            self.0.push(format!("{}-{}", m.line_number, m.line));
+            Ok(())
+        }
+
+        fn context_break(&mut self) -> io::Result<()> {
+            self.0.push(String::from("--"));
+            Ok(())
+        }
+
+        fn end(&mut self, stats: &Stats) -> io::Result<()> {
+            self.0.push(format!("end {}", stats.matched_lines));
+            Ok(())
+        }
+
+        fn error(&mut self, _path: Option<&Path>, _err: &dyn Error) {
+            self.0.push(String::from("error"));
+        }
+    }
+
+    #[test]
+    fn searcher_with_a_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("poem.txt");
+        std::fs::write(&path, "a\nFrog\nb\nc\nd\nfrog\n").unwrap();
+
+        let searcher = Searcher::builder()
+            .pattern("frog")
+            .ignore_case(true)
+            .context(0, 1)
+            .path(path.to_string_lossy())
+            .path(dir.path().join("missing").to_string_lossy())
+            .build()
+            .unwrap();
+
+        let mut events = Events::default();
+        assert_eq!(1, searcher.search_files(&mut events).unwrap());
+        assert_eq!(
+            vec![
+                "begin poem.txt",
+                "2:Frog",
+                "3-b",
+                "--",
+                "6:frog",
+                "end 2",
+                "error"
+            ],
+            events.0
+        );
+
+        let mut events = Events::default();
+        let stats = searcher.search_reader(&b"frog"[..], &mut events).unwrap();
+        assert_eq!(1, stats.matched_lines);
+        assert_eq!(vec!["1:frog"], events.0);
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let config = Config {
+            patterns: vec![String::from("frog")],
+            line_number: true,
+            byte_offset: true,
+            ..Config::default()
+        };
+        let contents = b"caf\xe9\r\nlike a frog \xff\nbog";
+
+        assert_eq!(
+            (1, String::from("2:6:like a frog \u{fffd}\n")),
+            search(contents, &config)
+        );
+    }
+
+    #[test]
+    fn context_outlives_the_read_buffer() {
+        let config = Config {
+            patterns: vec![String::from("c")],
+            before_context: 2,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            (1, String::from("a\nb\nc\n")),
+            search(b"a\nb\nc\nd\n", &config)
+        );
+    }
+
+    #[test]
+    fn replace_selected_lines() {
+        let config = Config {
+            patterns: vec![String::from("frog")],
+            replace: Some(String::from("toad")),
+            after_context: 1,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            (1, String::from("a toad, toads\nbog\n")),
+            search(b"no\na frog, frogs\nbog\n", &config)
+        );
+    }
+
+    #[test]
+    fn binary_files() {
+        let mut config = Config {
+            patterns: vec![String::from("frog")],
+            ..Config::default()
+        };
+        let contents = b"\x7fELF\x00\x01frog\nfrog\n";
+        let matcher = Matcher::new(&config).unwrap();
+        let mut out = Vec::new();
+        let mut printer = Printer::new(&mut out, &config, false);
+
+        printer.begin(Path::new("a.out")).unwrap();
+        let stats =
+            search_reader(&matcher, &contents[..], &config, &mut printer)
+                .unwrap();
+        assert_eq!(1, stats.matched_lines);
+        assert_eq!(b"Binary file a.out matches\n", &out[..]);
+
+        config.text = true;
+        assert_eq!(2, search(contents, &config).0);
+    }
+
+    #[test]
+    fn count_matches() {
+        let mut config = Config {
+            patterns: vec![String::from("a")],
+            count: true,
+            count_matches: true,
+            ..Config::default()
+        };
+        let matcher = Matcher::new(&config).unwrap();
+        let mut printer = Printer::new(Vec::new(), &config, false);
+        let contents = b"banana\nb\nab\n";
+
+        let stats =
+            search_reader(&matcher, &contents[..], &config, &mut printer)
+                .unwrap();
+        assert_eq!((2, 4), (stats.matched_lines, stats.matches));
+
+        // The lines are what's selected with -v
+        config.invert_match = true;
+        let stats =
+            search_reader(&matcher, &contents[..], &config, &mut printer)
+                .unwrap();
+        assert_eq!((1, 1), (stats.matched_lines, stats.matches));
+    }
+
+    #[test]
+    fn count_only() {
+        let config = Config {
+            patterns: vec![String::from("a")],
+            invert_match: true,
+            count: true,
+            ..Config::default()
+        };
+
+        assert_eq!((2, String::new()), search(b"a\nb\nab\nc", &config));
+    }
+
+    #[test]
+    fn max_count_keeps_trailing_context() {
+        let config = Config {
+            patterns: vec![String::from("x")],
+            max_count: Some(2),
+            after_context: 1,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            (2, String::from("x1\nx2\nx3\n")),
+            search(b"x1\nx2\nx3\nx4\n", &config)
+        );
+    }
+
+    #[test]
+    fn files_with_matches_stops_at_the_first() {
+        let config = Config {
+            patterns: vec![String::from("x")],
+            files_with_matches: true,
+            ..Config::default()
+        };
+
+        assert_eq!((1, String::new()), search(b"a\nx\nx\n", &config));
+    }
+}
@@ -0,0 +1,51 @@
+use std::{error::Error, io, path::Path};
+
+use crate::{searcher::Stats, Match};
+
+/// Where a [`Searcher`](crate::Searcher) sends what it finds.
+///
+/// `run` prints everything with a sink of its own, implement this to
+/// collect the results or send them anywhere else. Only `matched` is
+/// required, the rest do nothing by default. Returning an error stops
+/// the search.
+pub trait Sink {
+    /// A file is about to be searched.
+    fn begin(&mut self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The lines of a file begun before carry on after some of another
+    /// file's, with --follow. Begins it again by default.
+    fn resume(&mut self, path: &Path) -> io::Result<()> {
+        self.begin(path)
+    }
+
+    /// A selected line, one that matched or didn't with -v.
+    fn matched(&mut self, m: &Match) -> io::Result<()>;
+
+    /// A line before or after a selected one, with -A, -B or -C.
+    fn context(&mut self, _m: &Match) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Lines were skipped between two groups of context.
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A binary file had a selected line. The search of the file stops
+    /// there, none of its lines are handed over.
+    fn binary_matched(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Done with the current file, `stats` are what it found.
+    fn end(&mut self, _stats: &Stats) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A file or directory couldn't be searched, the search carries on
+    /// with the rest. There's no path when the walk already put it in
+    /// the error.
+    fn error(&mut self, _path: Option<&Path>, _err: &dyn Error) {}
+}
@@ -0,0 +1,314 @@
+use std::{
+    iter,
+    path::{Path, PathBuf},
+};
+
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    types::{Types, TypesBuilder},
+    WalkBuilder,
+};
+
+use crate::{Config, ConfigError};
+
+/// The path that stands for standard input.
+pub const STDIN: &str = "-";
+
+/// Walk every path in `config` and yield the files to search.
+///
+/// Directories are walked recursively. Hidden files and anything matched
+/// by a `.gitignore` or `.ignore` file are skipped unless `--hidden` or
+/// `--no-ignore` is given, so are the files left out by `-t`, `-T` and
+/// `--glob`. Paths named explicitly on the command line are always
+/// searched, and `-` is passed through for standard input.
+pub fn files(
+    config: &Config,
+) -> Box<dyn Iterator<Item = Result<PathBuf, ignore::Error>> + '_> {
+    // Config::build checked these already, unless it was skipped
+    let types = match types(config) {
+        Ok(types) => types,
+        Err(err) => return Box::new(iter::once(Err(err))),
+    };
+
+    Box::new(config.paths.iter().flat_map(
+        move |path| -> Box<dyn Iterator<Item = _>> {
+            if path == STDIN {
+                Box::new(iter::once(Ok(PathBuf::from(STDIN))))
+            } else {
+                walk(path, config, types.clone())
+            }
+        },
+    ))
+}
+
+/// Check the file filters of `config` before anything is walked.
+pub fn check_filters(config: &Config) -> Result<(), ConfigError> {
+    let invalid = |flag: &str, value: &str| ConfigError::InvalidValue {
+        flag: String::from(flag),
+        value: value.to_string(),
+    };
+
+    types(config).map_err(|err| match err {
+        ignore::Error::UnrecognizedFileType(name) => {
+            match config.types.contains(&name) {
+                true => invalid("--type", &name),
+                false => invalid("--type-not", &name),
+            }
+        }
+        ignore::Error::WithPath { path, .. } => {
+            invalid("--type-add", &path.to_string_lossy())
+        }
+        // A definition with a glob that doesn't parse
+        err => invalid("--type-add", &glob_of(&err)),
+    })?;
+    overrides(".", config).map_err(|err| invalid("--glob", &glob_of(&err)))?;
+    Ok(())
+}
+
+// The glob an error is about, if it says
+fn glob_of(err: &ignore::Error) -> String {
+    match err {
+        ignore::Error::Glob {
+            glob: Some(glob), ..
+        } => glob.clone(),
+        err => err.to_string(),
+    }
+}
+
+// -t and -T, out of the built-in definitions and the ones from
+// --type-add. Nothing is filtered without them.
+fn types(config: &Config) -> Result<Types, ignore::Error> {
+    let mut builder = TypesBuilder::new();
+    builder.add_defaults();
+    for def in &config.type_add {
+        // The error alone doesn't say which definition it is
+        builder
+            .add_def(def)
+            .map_err(|err| ignore::Error::WithPath {
+                path: PathBuf::from(def),
+                err: Box::new(err),
+            })?;
+    }
+    for name in &config.types {
+        builder.select(name);
+    }
+    for name in &config.types_not {
+        builder.negate(name);
+    }
+    builder.build()
+}
+
+// --glob, relative to the path being walked. A glob starting with !
+// excludes, and once there's one without only what it matches is left.
+fn overrides(path: &str, config: &Config) -> Result<Override, ignore::Error> {
+    let mut builder = OverrideBuilder::new(path);
+    for glob in &config.globs {
+        builder.add(glob)?;
+    }
+    builder.build()
+}
+
+fn walk(
+    path: &str,
+    config: &Config,
+    types: Types,
+) -> Box<dyn Iterator<Item = Result<PathBuf, ignore::Error>>> {
+    let overrides = match overrides(path, config) {
+        Ok(overrides) => overrides,
+        Err(err) => return Box::new(iter::once(Err(err))),
+    };
+
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .hidden(!config.hidden)
+        .ignore(!config.no_ignore)
+        .git_ignore(!config.no_ignore)
+        .git_global(!config.no_ignore)
+        .git_exclude(!config.no_ignore)
+        .parents(!config.no_ignore)
+        // Honour .gitignore even outside of a git repository
+        .require_git(false)
+        .types(types)
+        .overrides(overrides);
+
+    if config.sort_files {
+        builder.sort_by_file_name(|a, b| a.cmp(b));
+    }
+
+    Box::new(builder.build().filter_map(|entry| match entry {
+        Ok(entry) => {
+            // Skip directories, only files are searched
+            let is_file = entry.file_type().is_some_and(|t| !t.is_dir());
+            is_file.then(|| Ok(entry.into_path()))
+        }
+        Err(err) => Some(Err(err)),
+    }))
+}
+
+/// Whether results need to be prefixed with the file they came from.
+pub fn multiple_files(config: &Config) -> bool {
+    config.paths.len() > 1
+        || config
+            .paths
+            .first()
+            .is_some_and(|path| Path::new(path).is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn config(paths: Vec<String>) -> Config {
+        Config {
+            patterns: vec![String::from("needle")],
+            paths,
+            ..Config::default()
+        }
+    }
+
+    fn names(config: &Config) -> Vec<String> {
+        let mut names: Vec<String> = files(config)
+            .map(|path| {
+                let path = path.unwrap();
+                path.file_name().unwrap().to_string_lossy().into_owned()
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn walks_recursively_and_honours_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join(".ignore"), "*.log\n").unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "").unwrap();
+        fs::write(root.join("src/debug.log"), "").unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+        fs::write(root.join(".hidden.rs"), "").unwrap();
+
+        let mut config = config(vec![root.to_string_lossy().into_owned()]);
+        assert_eq!(vec!["lib.rs", "main.rs"], names(&config));
+
+        config.hidden = true;
+        config.no_ignore = true;
+        assert_eq!(
+            vec![
+                ".gitignore",
+                ".hidden.rs",
+                ".ignore",
+                "debug.log",
+                "lib.rs",
+                "main.rs",
+                "out.rs"
+            ],
+            names(&config)
+        );
+    }
+
+    #[test]
+    fn explicit_paths_are_always_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join(".ignore"), "*.log\n").unwrap();
+        fs::write(root.join("debug.log"), "").unwrap();
+        fs::write(root.join("poem.txt"), "").unwrap();
+
+        let config = config(vec![
+            root.join("debug.log").to_string_lossy().into_owned(),
+            root.join("poem.txt").to_string_lossy().into_owned(),
+        ]);
+        assert_eq!(vec!["debug.log", "poem.txt"], names(&config));
+        assert!(multiple_files(&config));
+        // A Config built by hand may have no paths at all
+        assert!(!multiple_files(&Config::default()));
+    }
+
+    #[test]
+    fn types_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join("main.rs"), "").unwrap();
+        fs::write(root.join("README.md"), "").unwrap();
+        fs::write(root.join("notes.txt"), "").unwrap();
+        fs::write(root.join("target/out.rs"), "").unwrap();
+
+        let mut config = config(vec![root.to_string_lossy().into_owned()]);
+        config.types = vec![String::from("rust")];
+        assert_eq!(vec!["main.rs", "out.rs"], names(&config));
+
+        config.globs = vec![String::from("!target/**")];
+        assert_eq!(vec!["main.rs"], names(&config));
+
+        config.types.clear();
+        config.types_not = vec![String::from("markdown")];
+        assert_eq!(vec!["main.rs", "notes.txt"], names(&config));
+
+        config.types_not.clear();
+        config.globs = vec![String::from("*.txt")];
+        assert_eq!(vec!["notes.txt"], names(&config));
+
+        config.globs.clear();
+        config.type_add = vec![String::from("notes:*.txt")];
+        config.types = vec![String::from("notes")];
+        assert_eq!(vec!["notes.txt"], names(&config));
+        assert_eq!(Ok(()), check_filters(&config));
+
+        config.types = vec![String::from("nope")];
+        assert!(files(&config).next().unwrap().is_err());
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--type"),
+                value: String::from("nope"),
+            }),
+            check_filters(&config)
+        );
+
+        config.types = vec![String::from("rust")];
+        config.types_not = vec![String::from("nope")];
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--type-not"),
+                value: String::from("nope"),
+            }),
+            check_filters(&config)
+        );
+
+        // A glob only gets compiled when its type is selected
+        config.types = vec![String::from("notes")];
+        config.types_not.clear();
+        for def in ["notes", "notes:*.{txt"] {
+            config.type_add = vec![String::from(def)];
+            assert!(matches!(
+                check_filters(&config),
+                Err(ConfigError::InvalidValue { flag, .. }) if flag == "--type-add"
+            ));
+        }
+
+        config.type_add.clear();
+        config.types.clear();
+        config.globs = vec![String::from("*.{rs")];
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: String::from("--glob"),
+                value: String::from("*.{rs"),
+            }),
+            check_filters(&config)
+        );
+    }
+
+    #[test]
+    fn stdin_is_passed_through() {
+        let config = config(vec![String::from("-"), String::from("-")]);
+        let paths: Vec<PathBuf> = files(&config).map(Result::unwrap).collect();
+
+        assert_eq!(vec![PathBuf::from("-"), PathBuf::from("-")], paths);
+    }
+}
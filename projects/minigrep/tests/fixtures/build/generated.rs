@@ -0,0 +1,2 @@
+// Generated, never searched
+fn frog() {}
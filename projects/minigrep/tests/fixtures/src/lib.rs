@@ -0,0 +1,8 @@
+/// Tells the frog where the bog is.
+pub fn croak(times: usize) -> String {
+    "ribbit ".repeat(times)
+}
+
+pub fn leap(distance: u32) -> u32 {
+    distance * 2
+}
@@ -0,0 +1,16 @@
+use std::process;
+
+fn main() {
+    if let Err(err) = run(
+        "frog",
+        "bog",
+    ) {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+}
+
+fn run(query: &str, path: &str) -> Result<(), String> {
+    println!("searching {path} for {query}");
+    Ok(())
+}
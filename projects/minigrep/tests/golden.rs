@@ -0,0 +1,89 @@
+//! Runs the minigrep binary on the files in `tests/fixtures` for each
+//! case in `tests/golden`, and compares what it prints with what it
+//! printed when the case was written.
+//!
+//! A case is a directory with:
+//!
+//! - `args`, the arguments, one per line
+//! - `stdout`, what's expected on standard output
+//! - `status`, the exit status when it isn't 0
+//!
+//! To add a case write its `args` and run
+//! `UPDATE_GOLDEN=1 cargo test --test golden`, which writes what the
+//! binary prints as the expected output of every case. Check the diff
+//! before committing it.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+struct Output {
+    stdout: String,
+    stderr: String,
+    status: i32,
+}
+
+#[test]
+fn golden_files() {
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut cases: Vec<PathBuf> = fs::read_dir(tests.join("golden"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no cases in tests/golden");
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_name().unwrap().to_string_lossy();
+        let args = fs::read_to_string(case.join("args")).unwrap();
+        let output = minigrep(&tests.join("fixtures"), args.lines());
+
+        if update {
+            fs::write(case.join("stdout"), &output.stdout).unwrap();
+            let status = case.join("status");
+            match output.status {
+                0 => _ = fs::remove_file(status),
+                code => fs::write(status, format!("{code}\n")).unwrap(),
+            }
+            continue;
+        }
+
+        let stdout = fs::read_to_string(case.join("stdout")).unwrap();
+        let status = fs::read_to_string(case.join("status"))
+            .map_or(0, |status| status.trim().parse().unwrap());
+        if output.stdout != stdout || output.status != status {
+            failures.push(format!(
+                "{name}: expected status {status} and\n{stdout}\n\
+                 got status {} and\n{}\nwith stderr\n{}",
+                output.status, output.stdout, output.stderr
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Run the binary in `dir`, away from the config file and environment
+// of whoever runs the tests
+fn minigrep<'a>(dir: &Path, args: impl Iterator<Item = &'a str>) -> Output {
+    let home = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .args(args)
+        .current_dir(dir)
+        .env("HOME", home.path())
+        .env_remove("MINIGREP_CONFIG")
+        .env_remove("IGNORE_CASE")
+        .output()
+        .unwrap();
+
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code().unwrap(),
+    }
+}
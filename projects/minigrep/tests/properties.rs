@@ -0,0 +1,165 @@
+//! Checks the searches against the obvious, slow way of doing the same
+//! on random text.
+//!
+//! The text is made of a few characters only, so that queries actually
+//! show up in it and line endings, `\r` included, are everywhere.
+
+use std::io;
+
+use minigrep::{Match, Searcher, Sink};
+use quickcheck::{Arbitrary, Gen, QuickCheck};
+
+const ALPHABET: &[char] = &['a', 'b', 'A', 'é', 'É', ' ', '\n', '\r'];
+
+#[derive(Debug, Clone)]
+struct Text(String);
+
+impl Arbitrary for Text {
+    fn arbitrary(g: &mut Gen) -> Text {
+        let len = usize::arbitrary(g) % g.size();
+        Text((0..len).map(|_| *g.choose(ALPHABET).unwrap()).collect())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Text>> {
+        // Fewer characters, still out of the alphabet
+        let text = self.0.clone();
+        Box::new(
+            (0..text.chars().count())
+                .map(move |i| {
+                    let mut chars: Vec<char> = text.chars().collect();
+                    chars.remove(i);
+                    Text(chars.into_iter().collect())
+                })
+                .rev(),
+        )
+    }
+}
+
+// The lines of `contents` with their numbers, split like str::lines
+// says: at "\n" or "\r\n", and a last line ending is optional
+fn lines(contents: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let line = match rest.find('\n') {
+            Some(end) => {
+                let line = &rest[..end];
+                rest = &rest[end + 1..];
+                line.strip_suffix('\r').unwrap_or(line)
+            }
+            None => std::mem::take(&mut rest),
+        };
+        lines.push((lines.len() + 1, line));
+    }
+    lines
+}
+
+// Whether `query` starts at any character of `line`
+fn contains(line: &str, query: &str) -> bool {
+    line.char_indices()
+        .map(|(i, _)| i)
+        .chain([line.len()])
+        .any(|i| line[i..].starts_with(query))
+}
+
+fn reference<'a>(query: &str, contents: &'a str) -> Vec<(usize, &'a str)> {
+    lines(contents)
+        .into_iter()
+        .filter(|(_, line)| contains(line, query))
+        .collect()
+}
+
+fn check<A: quickcheck::Testable>(property: A) {
+    QuickCheck::new().tests(1000).quickcheck(property);
+}
+
+#[test]
+fn search_finds_the_lines_containing_the_query() {
+    fn property(query: Text, contents: Text) -> bool {
+        let expected: Vec<&str> = reference(&query.0, &contents.0)
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect();
+        minigrep::search(&query.0, &contents.0) == expected
+    }
+    check(property as fn(Text, Text) -> bool);
+}
+
+#[test]
+fn case_insensitive_is_lowercase_search() {
+    fn property(query: Text, contents: Text) -> bool {
+        // Every character in the alphabet lowercases to one character,
+        // so offsets don't move
+        let lower = contents.0.to_lowercase();
+        let expected: Vec<&str> = reference(&query.0.to_lowercase(), &lower)
+            .into_iter()
+            .map(|(number, _)| lines(&contents.0)[number - 1].1)
+            .collect();
+        minigrep::search_case_insensitive(&query.0, &contents.0) == expected
+    }
+    check(property as fn(Text, Text) -> bool);
+}
+
+// Collects the selected lines the way an embedding crate would
+#[derive(Default)]
+struct Selected(Vec<(usize, String)>);
+
+impl Sink for Selected {
+    fn matched(&mut self, m: &Match) -> io::Result<()> {
+        self.0.push((m.line_number, m.line.to_string()));
+        Ok(())
+    }
+}
+
+#[test]
+fn streaming_search_agrees() {
+    fn property(query: Text, contents: Text, invert: bool) -> bool {
+        let searcher = Searcher::builder()
+            .pattern(query.0.clone())
+            .invert_match(invert)
+            .build()
+            .unwrap();
+        let mut selected = Selected::default();
+        searcher
+            .search_reader(contents.0.as_bytes(), &mut selected)
+            .unwrap();
+
+        let expected: Vec<(usize, String)> = lines(&contents.0)
+            .into_iter()
+            .filter(|(_, line)| contains(line, &query.0) != invert)
+            .map(|(number, line)| (number, line.to_string()))
+            .collect();
+        selected.0 == expected
+    }
+    check(property as fn(Text, Text, bool) -> bool);
+}
+
+#[test]
+fn literal_lines_agree() {
+    fn property(query: Text, contents: Text) -> bool {
+        let found: Vec<(usize, &str)> =
+            minigrep::literal_lines(&query.0, contents.0.as_bytes())
+                .map(|(number, range)| (number, &contents.0[range]))
+                .collect();
+        found == reference(&query.0, &contents.0)
+    }
+    check(property as fn(Text, Text) -> bool);
+}
+
+#[test]
+fn hits_are_where_the_query_is() {
+    fn property(query: Text, contents: Text) -> bool {
+        let searcher = Searcher::builder()
+            .pattern(query.0.clone())
+            .build()
+            .unwrap();
+        minigrep::find_matches(searcher.matcher(), &contents.0)
+            .iter()
+            .all(|m| {
+                m.columns
+                    .iter()
+                    .all(|range| m.line[range.clone()] == query.0)
+            })
+    }
+    check(property as fn(Text, Text) -> bool);
+}